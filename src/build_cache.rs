@@ -0,0 +1,316 @@
+use crate::configuration::{AppConfig, MapSidecar};
+use crate::process_images::MapSource;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CACHE_FILENAME: &str = ".buggy-cache.toml";
+// Contact sheet thumbnails of cached territories, since not every layout format can be
+// read back to make them again, e.g. SVG
+const THUMBNAILS_DIRECTORY: &str = ".buggy-thumbnails";
+
+// FNV-1a, small and stable across builds unlike the standard library hasher
+struct Fingerprint(u64);
+
+impl Fingerprint {
+    fn new() -> Self {
+        Fingerprint(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // Keep consecutive inputs apart, so `ab` + `c` differs from `a` + `bc`
+        self.0 ^= bytes.len() as u64;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    // A missing file is hashed as such, so it changes the fingerprint too
+    fn write_file(&mut self, path: &Path) {
+        self.write(path.to_string_lossy().as_bytes());
+        match fs::read(path) {
+            Ok(data) => self.write(&data),
+            Err(_) => self.write(b"missing"),
+        }
+    }
+}
+
+// Hash of everything a territory's layouts are made from: the map tiles and sidecar,
+// the configuration, the fonts and the images drawn on every layout
+pub fn territory_fingerprint(
+    config: &AppConfig,
+    map: &MapSource,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut fingerprint = Fingerprint::new();
+    fingerprint.write(env!("CARGO_PKG_VERSION").as_bytes());
+    // Settings that don't change the layouts, such as the other outputs of a run
+    let mut layout_config = config.clone();
+    layout_config.overview = None;
+    layout_config.contact_sheet = None;
+    layout_config.report = None;
+    layout_config.log = None;
+    layout_config.preview_graphics = Default::default();
+    fingerprint.write(toml::to_string(&layout_config)?.as_bytes());
+    for tile in &map.tiles {
+        fingerprint.write_file(tile);
+    }
+    fingerprint.write_file(&MapSidecar::path_for(&map.path));
+    fingerprint.write_file(Path::new(&config.font.path_regular));
+    fingerprint.write_file(Path::new(&config.font.path_bold));
+    for element in &config.layout.images {
+        fingerprint.write_file(Path::new(&element.path));
+    }
+    if let Some(image) = config
+        .layout
+        .background
+        .as_ref()
+        .and_then(|background| background.image.as_ref())
+    {
+        fingerprint.write_file(Path::new(image));
+    }
+    Ok(format!("{:016x}", fingerprint.0))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    fingerprint: String,
+    pub outputs: Vec<PathBuf>,
+    #[serde(default)]
+    pub thumbnails: Vec<PathBuf>,
+}
+
+impl CacheEntry {
+    fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.outputs.iter().chain(&self.thumbnails)
+    }
+}
+
+// Fingerprint and output files of every territory rendered by earlier runs, saved in
+// the output directory and keyed by the map path
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct BuildCache {
+    #[serde(default)]
+    territories: BTreeMap<String, CacheEntry>,
+}
+
+impl BuildCache {
+    fn path(output_directory: &Path) -> PathBuf {
+        output_directory.join(CACHE_FILENAME)
+    }
+
+    // An unreadable cache is treated as empty, which only means rendering everything again
+    pub fn load(output_directory: &Path) -> Self {
+        fs::read_to_string(Self::path(output_directory))
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, output_directory: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path(output_directory);
+        fs::write(&path, toml::to_string(self)?)
+            .map_err(|e| format!("Failed to write {} - {}", path.display(), e).into())
+    }
+
+    // Contact sheet thumbnail of one page of a territory, e.g. `.buggy-thumbnails/12-zone-p1.png`
+    pub fn thumbnail_path(output_directory: &Path, map: &Path, page: usize) -> PathBuf {
+        let filename = format!("{}-p{}.png", map.to_string_lossy(), page);
+        output_directory.join(THUMBNAILS_DIRECTORY).join(filename)
+    }
+
+    // Files of a territory rendered with the same fingerprint, if they all still exist
+    pub fn up_to_date(&self, map: &Path, fingerprint: &str) -> Option<&CacheEntry> {
+        let entry = self.territories.get(&map.to_string_lossy().to_string())?;
+        (entry.fingerprint == fingerprint && entry.files().all(|file| file.exists()))
+            .then_some(entry)
+    }
+
+    // Record the files of a rendered territory. Returns the files of its earlier render
+    // that this one didn't write again, e.g. the `-p3` page of a map now split in two
+    pub fn insert(
+        &mut self,
+        map: &Path,
+        fingerprint: String,
+        outputs: Vec<PathBuf>,
+        thumbnails: Vec<PathBuf>,
+    ) -> Vec<PathBuf> {
+        let entry = CacheEntry {
+            fingerprint,
+            outputs,
+            thumbnails,
+        };
+        let previous = self
+            .territories
+            .insert(map.to_string_lossy().to_string(), entry);
+        self.unowned(
+            previous
+                .iter()
+                .flat_map(|previous| previous.files())
+                .cloned(),
+        )
+    }
+
+    // The files no territory in the cache writes. Different maps can write the same
+    // file, e.g. after renaming `12-Zone.png` to `12-zone.png`
+    fn unowned(&self, files: impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+        files
+            .filter(|file| {
+                !self
+                    .territories
+                    .values()
+                    .any(|entry| entry.files().any(|owned| owned == file))
+            })
+            .collect()
+    }
+
+    // Forget the territories whose map is gone and return their files
+    pub fn retain(&mut self, maps: &[&Path]) -> Vec<PathBuf> {
+        let names: Vec<_> = maps
+            .iter()
+            .map(|map| map.to_string_lossy().to_string())
+            .collect();
+        let mut stale = Vec::new();
+        self.territories.retain(|name, entry| {
+            let keep = names.contains(name);
+            if !keep {
+                stale.extend(entry.files().cloned());
+            }
+            keep
+        });
+        self.unowned(stale.into_iter())
+    }
+
+    pub fn remove(&mut self, map: &Path) {
+        self.territories.remove(&map.to_string_lossy().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(parts: &[&[u8]]) -> u64 {
+        let mut fingerprint = Fingerprint::new();
+        for part in parts {
+            fingerprint.write(part);
+        }
+        fingerprint.0
+    }
+
+    #[test]
+    fn fingerprint_is_stable() {
+        assert_eq!(fingerprint(&[b"abc"]), fingerprint(&[b"abc"]));
+        assert_ne!(fingerprint(&[b"abc"]), fingerprint(&[b"abd"]));
+        // Known value, so a change of the hash that would invalidate every cache shows up
+        assert_eq!(fingerprint(&[]), 0xcbf29ce484222325);
+    }
+
+    #[test]
+    fn fingerprint_keeps_inputs_apart() {
+        assert_ne!(fingerprint(&[b"ab", b"c"]), fingerprint(&[b"a", b"bc"]));
+        assert_ne!(fingerprint(&[b"abc"]), fingerprint(&[b"abc", b""]));
+    }
+
+    #[test]
+    fn fingerprint_of_a_missing_file() {
+        let path = Path::new("no/such/file.png");
+        let mut missing = Fingerprint::new();
+        missing.write_file(path);
+        let mut empty = Fingerprint::new();
+        empty.write(path.to_string_lossy().as_bytes());
+        empty.write(b"");
+        assert_ne!(missing.0, empty.0);
+    }
+
+    #[test]
+    fn insert_returns_files_no_longer_written() {
+        let map = Path::new("12-zone");
+        let pages = |count: usize| -> Vec<PathBuf> {
+            (1..=count)
+                .map(|page| PathBuf::from(format!("12-zone-p{}.png", page)))
+                .collect()
+        };
+        let mut cache = BuildCache::default();
+        assert!(cache
+            .insert(map, "a".into(), pages(3), Vec::new())
+            .is_empty());
+        let stale = cache.insert(map, "b".into(), pages(2), Vec::new());
+        assert_eq!(stale, vec![PathBuf::from("12-zone-p3.png")]);
+        assert!(cache
+            .insert(map, "b".into(), pages(2), Vec::new())
+            .is_empty());
+    }
+
+    #[test]
+    fn retain_returns_files_of_removed_maps() {
+        let mut cache = BuildCache::default();
+        let outputs = vec![PathBuf::from("7-zone.png")];
+        let thumbnails = vec![PathBuf::from(".buggy-thumbnails/7-zone-p1.png")];
+        cache.insert(Path::new("7-zone"), "a".into(), outputs, thumbnails);
+        cache.insert(Path::new("12-zone"), "a".into(), Vec::new(), Vec::new());
+        assert!(cache.retain(&[Path::new("7-zone")]).is_empty());
+        assert_eq!(
+            cache.retain(&[Path::new("12-zone")]),
+            vec![
+                PathBuf::from("7-zone.png"),
+                PathBuf::from(".buggy-thumbnails/7-zone-p1.png")
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_files_another_map_writes() {
+        // A case-only rename leaves the old key in the cache with the same output
+        let output = vec![PathBuf::from("layouts/12-casal-monastero.png")];
+        let mut cache = BuildCache::default();
+        cache.insert(
+            Path::new("12-Casal-Monastero"),
+            "a".into(),
+            output.clone(),
+            Vec::new(),
+        );
+        cache.insert(
+            Path::new("12-casal-monastero"),
+            "b".into(),
+            output.clone(),
+            Vec::new(),
+        );
+        assert!(cache.retain(&[Path::new("12-casal-monastero")]).is_empty());
+        assert!(cache
+            .up_to_date(Path::new("12-Casal-Monastero"), "a")
+            .is_none());
+
+        // A sidecar gives another map the name of this one
+        let mut cache = BuildCache::default();
+        cache.insert(Path::new("12-zone"), "a".into(), output.clone(), Vec::new());
+        cache.insert(Path::new("scan"), "a".into(), output.clone(), Vec::new());
+        let renamed = vec![PathBuf::from("layouts/13-zone.png")];
+        assert!(cache
+            .insert(Path::new("12-zone"), "b".into(), renamed, Vec::new())
+            .is_empty());
+    }
+
+    #[test]
+    fn up_to_date_needs_every_file() {
+        let map = Path::new("12-zone");
+        let mut cache = BuildCache::default();
+        cache.insert(
+            map,
+            "a".into(),
+            vec![PathBuf::from("Cargo.toml")],
+            Vec::new(),
+        );
+        assert!(cache.up_to_date(map, "a").is_some());
+        assert!(cache.up_to_date(map, "b").is_none());
+        cache.insert(
+            map,
+            "a".into(),
+            vec![PathBuf::from("missing.png")],
+            Vec::new(),
+        );
+        assert!(cache.up_to_date(map, "a").is_none());
+    }
+}
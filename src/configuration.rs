@@ -1,5 +1,11 @@
 use crossterm::style::{Color, Stylize};
+use image::imageops::FilterType;
+use log::LevelFilter;
+use serde::de::{value::StrDeserializer, DeserializeOwned};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
 use std::str::FromStr;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -9,15 +15,235 @@ pub enum Alignment {
     Right,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+// How the map is scaled into the map area
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum MapPlacement {
+    // Scale to fit entirely inside the area
+    #[default]
+    Fit,
+    // Scale to cover the whole area, cropping the overflow
+    Fill,
+    // Scale to the area size, ignoring the aspect ratio
+    Stretch,
+    // Keep the original size, cropping the overflow
+    None,
+}
+
+// Where the map sits in the map area when it does not fill it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    #[default]
+    Bottom,
+    BottomRight,
+}
+
+// Resampling filter used when scaling the map, from the sharpest to the smoothest
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+// File format of the saved layouts
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    // Lossless WebP
+    WebP,
+    // Uncompressed TIFF, as print shops often ask for
+    Tiff,
+    // Vector text over the rest of the layout embedded as an image
+    Svg,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Tiff => "tif",
+            OutputFormat::Svg => "svg",
+        }
+    }
+}
+
+// Way of drawing images in the terminal
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum TerminalGraphics {
+    // Kitty or sixel graphics when the terminal is known to support them
+    #[default]
+    Auto,
+    Kitty,
+    Sixel,
+    // Colored Unicode half blocks, understood by any terminal with true color
+    HalfBlocks,
+}
+
+// Most detailed messages written to the log file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    // Each processed territory and its timings
+    #[default]
+    Info,
+    // Every step of the rendering
+    Debug,
+}
+
+impl LogLevel {
+    pub fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+        }
+    }
+}
+
+// File format of the run report
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    // One row per output file, or per input file when nothing was saved
+    Csv,
+}
+
+// How the printer palette approximates the colors it does not have
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum Dither {
+    // Use the nearest palette color
+    None,
+    // Regular pattern of dots, crisp on laser printers
+    Ordered,
+    // Spread the color error over the neighbouring pixels
+    #[default]
+    FloydSteinberg,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppConfig {
     pub font: FontConfig,
     pub layout: LayoutConfig,
     pub map: MapConfig,
     pub output_directory: String,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    // From 1 to 100, only used for JPEG
+    #[serde(default = "default_jpeg_quality")]
+    pub jpeg_quality: u8,
+    // How the preview draws layouts in the terminal
+    #[serde(default)]
+    pub preview_graphics: TerminalGraphics,
+    #[serde(default)]
+    pub zones: Vec<ZoneConfig>,
+    pub overview: Option<OverviewConfig>,
+    pub contact_sheet: Option<ContactSheetConfig>,
+    pub print: Option<PrintConfig>,
+    pub report: Option<ReportConfig>,
+    pub log: Option<LogConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn default_jpeg_quality() -> u8 {
+    90
+}
+
+// Reduce the layouts to a few colors for cheap printers, saved as indexed PNG.
+// Only used with the PNG output format
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrintConfig {
+    // Palette, e.g. `["#000000", "#ffffff", "#ed5858"]`, shades of gray when empty
+    #[serde(default)]
+    pub colors: Vec<String>,
+    // Number of shades of gray used when no colors are set, e.g. 4
+    pub gray_levels: u32,
+    #[serde(default)]
+    pub dither: Dither,
+}
+
+// Report of every processed map written to the output directory after each run,
+// for auditing and automated checks
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReportConfig {
+    // e.g. `report.json`
+    pub output_filename: String,
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+// Log file in the output directory, appended to by every run
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LogConfig {
+    // e.g. `buggy.log`
+    pub output_filename: String,
+    #[serde(default)]
+    pub level: LogLevel,
+}
+
+// Single image of the whole congregation area with every territory on it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OverviewConfig {
+    pub base_map: String,
+    pub output_filename: String,
+    pub font_size: f32,
+}
+
+// Index image with a thumbnail of every layout created by a run
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContactSheetConfig {
+    pub output_filename: String,
+    pub columns: u32,
+    pub thumbnail_width: u32,
+    pub font_size: f32,
+    // Caption under each thumbnail, e.g. `**<territory_number>** <zone_name>`
+    pub label: String,
+}
+
+// Color used to paint a zone's territories, e.g. `#ed5858` at 40% opacity
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ZoneConfig {
+    pub name: String,
+    pub color: String,
+    pub opacity: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FontConfig {
     pub path_regular: String,
     pub path_bold: String,
@@ -25,7 +251,7 @@ pub struct FontConfig {
     pub size_subtitle: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LayoutConfig {
     pub width: u32,
     pub height: u32,
@@ -34,12 +260,188 @@ pub struct LayoutConfig {
     pub text_title: String,
     pub text_subtitle_left: String,
     pub text_subtitle_right: String,
+    pub legend: Option<LegendConfig>,
+    pub qr_code: Option<QrCodeConfig>,
+    #[serde(default)]
+    pub images: Vec<ImageElementConfig>,
+    pub background: Option<BackgroundConfig>,
+    pub border: Option<BorderConfig>,
+    // Render at 2 to 4 times the size and scale down, for smoother text and lines
+    #[serde(default = "default_supersample")]
+    pub supersample: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn default_supersample() -> u32 {
+    1
+}
+
+// Background color and/or image covering the whole layout
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackgroundConfig {
+    pub color: Option<String>,
+    pub image: Option<String>,
+}
+
+// Border along the layout edges, `inset` pixels away from them
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BorderConfig {
+    pub width: u32,
+    pub color: String,
+    #[serde(default)]
+    pub inset: u32,
+    #[serde(default)]
+    pub radius: u32,
+}
+
+// Static image such as the congregation logo, scaled to fit the given box
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageElementConfig {
+    pub path: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// QR code linking to the territory online, e.g.
+// `https://maps.google.com/?q=<latitude>,<longitude>`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QrCodeConfig {
+    pub url: String,
+    pub size: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegendConfig {
+    pub corner: Corner,
+    pub font_size: f32,
+    pub title: String,
+    pub boundary_label: String,
+    #[serde(default)]
+    pub symbols: Vec<LegendSymbol>,
+}
+
+// Annotation drawn by hand on the maps, e.g. `12` for a house number
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegendSymbol {
+    pub symbol: String,
+    pub label: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MapConfig {
     pub maps_directory: String,
+    // Take the zone name from the map's folder, e.g. `maps/casal-monastero/12.png`
+    #[serde(default)]
+    pub zone_from_folder: bool,
     pub crop: MapCrop,
+    #[serde(default)]
+    pub placement: MapPlacement,
+    // Unset keeps the original layout, with the map at the bottom of the whole
+    // area inside the margins
+    #[serde(default)]
+    pub anchor: Option<Anchor>,
+    #[serde(default)]
+    pub resize_filter: ResizeFilter,
+    // Clockwise rotation in degrees applied after cropping
+    #[serde(default)]
+    pub rotation: f32,
+    // Turn the map by 90 degrees when that makes it larger in the layout
+    #[serde(default)]
+    pub auto_rotate: bool,
+    #[serde(default = "default_boundary_width")]
+    pub boundary_width: u32,
+    pub meters_per_pixel: Option<f32>,
+    pub north_arrow: Option<NorthArrowConfig>,
+    pub scale_bar: Option<ScaleBarConfig>,
+    pub frame: Option<FrameConfig>,
+    pub pages: Option<PagesConfig>,
+    pub enhance: Option<EnhanceConfig>,
+}
+
+// Adjustments applied to the map before it is resized, e.g. to darken pale screenshots.
+// A map's sidecar can override each of them
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct EnhanceConfig {
+    // Added to every channel, from -255 to 255
+    pub brightness: Option<i32>,
+    // Percentage, negative values reduce the contrast
+    pub contrast: Option<f32>,
+    // 1.0 keeps the colors, 0.0 removes them, above 1.0 makes them stronger
+    pub saturation: Option<f32>,
+    // Above 1.0 brightens the midtones, below 1.0 darkens them
+    pub gamma: Option<f32>,
+    // Unsharp mask blur radius and the difference below which pixels are left alone
+    pub sharpen: Option<f32>,
+    pub sharpen_threshold: Option<i32>,
+    // Convert to shades of gray for black-and-white printers
+    pub grayscale: Option<bool>,
+}
+
+impl EnhanceConfig {
+    // Settings of `overrides` where present, these ones otherwise
+    pub fn merge(&self, overrides: &EnhanceConfig) -> EnhanceConfig {
+        EnhanceConfig {
+            brightness: overrides.brightness.or(self.brightness),
+            contrast: overrides.contrast.or(self.contrast),
+            saturation: overrides.saturation.or(self.saturation),
+            gamma: overrides.gamma.or(self.gamma),
+            sharpen: overrides.sharpen.or(self.sharpen),
+            sharpen_threshold: overrides.sharpen_threshold.or(self.sharpen_threshold),
+            grayscale: overrides.grayscale.or(self.grayscale),
+        }
+    }
+}
+
+// Split maps that would shrink too much over several pages
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PagesConfig {
+    // Smallest scale factor accepted before the map is split, e.g. 0.6
+    pub min_scale: f32,
+    // Fraction of each page repeated on the next one, e.g. 0.1
+    pub overlap: f32,
+    // Page number text, e.g. `<page>/<total>`
+    pub label: String,
+    pub locator_corner: Corner,
+    pub locator_size: u32,
+}
+
+// Frame drawn around the map, with optionally rounded corners
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FrameConfig {
+    pub width: u32,
+    pub color: String,
+    #[serde(default)]
+    pub radius: u32,
+    pub shadow: Option<ShadowConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShadowConfig {
+    pub offset: i32,
+    pub blur: f32,
+    pub color: String,
+    pub opacity: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NorthArrowConfig {
+    pub corner: Corner,
+    pub size: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScaleBarConfig {
+    pub corner: Corner,
+    pub max_width: u32,
+    pub font_size: f32,
+}
+
+fn default_boundary_width() -> u32 {
+    8
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -50,7 +452,179 @@ pub struct MapCrop {
     pub right: u32,
 }
 
+// Per-map settings read from a `.toml` file next to the map image,
+// e.g. `maps/12-casal-monastero.toml`
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct MapSidecar {
+    pub meters_per_pixel: Option<f32>,
+    pub rotation: Option<f32>,
+    pub auto_rotate: Option<bool>,
+    // Top-left position of each tile in the stitched map, e.g. `[[0, 0], [1180, 0]]`
+    pub tile_offsets: Option<Vec<[i64; 2]>>,
+    // Territory outline in pixels of the overview base map, e.g. `[[120, 80], [300, 95], ...]`
+    pub boundary: Option<Vec<[f32; 2]>>,
+    pub enhance: Option<EnhanceConfig>,
+    // Crop used instead of `map.crop`, e.g. for a screenshot taken on another device
+    pub crop: Option<MapCrop>,
+    // Used instead of the territory number and zone name parsed from the filename
+    pub territory_number: Option<String>,
+    pub zone_name: Option<String>,
+    // Extra `<name>` placeholders for this territory, e.g. its coordinates
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+// Per-map settings that can be edited from the failure review
+pub enum SidecarField {
+    CropTop,
+    CropLeft,
+    CropBottom,
+    CropRight,
+    TerritoryNumber,
+    ZoneName,
+}
+
+impl MapSidecar {
+    pub fn path_for(map_path: &Path) -> std::path::PathBuf {
+        map_path.with_extension("toml")
+    }
+
+    pub fn load(map_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Self::path_for(map_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {} - {}", path.display(), e))?;
+        toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {} - {}", path.display(), e).into())
+    }
+
+    // Write the settings next to the map. Comments in an existing file are not kept
+    pub fn save(&self, map_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path_for(map_path);
+        fs::write(&path, toml::to_string(self)?)
+            .map_err(|e| format!("Failed to write {} - {}", path.display(), e).into())
+    }
+
+    // The value set for this map, None when it uses the default
+    pub fn get_field_value(&self, field: &SidecarField) -> Option<String> {
+        match field {
+            SidecarField::CropTop => self.crop.map(|crop| crop.top.to_string()),
+            SidecarField::CropLeft => self.crop.map(|crop| crop.left.to_string()),
+            SidecarField::CropBottom => self.crop.map(|crop| crop.bottom.to_string()),
+            SidecarField::CropRight => self.crop.map(|crop| crop.right.to_string()),
+            SidecarField::TerritoryNumber => self.territory_number.clone(),
+            SidecarField::ZoneName => self.zone_name.clone(),
+        }
+    }
+
+    // Set a field, or go back to the default with an empty value. A crop edge starts
+    // the crop from `default_crop`, and clearing one clears the whole crop
+    pub fn set_field_value(
+        &mut self,
+        field: &SidecarField,
+        value: String,
+        default_crop: MapCrop,
+    ) -> Result<(), String> {
+        let value = value.trim();
+        match field {
+            SidecarField::CropTop
+            | SidecarField::CropLeft
+            | SidecarField::CropBottom
+            | SidecarField::CropRight => {
+                if value.is_empty() {
+                    self.crop = None;
+                    return Ok(());
+                }
+                let v = value.parse::<u32>().map_err(|_| {
+                    format!("Crop values are whole numbers of pixels, not '{}'", value)
+                })?;
+                let mut crop = self.crop.unwrap_or(default_crop);
+                match field {
+                    SidecarField::CropTop => crop.top = v,
+                    SidecarField::CropLeft => crop.left = v,
+                    SidecarField::CropBottom => crop.bottom = v,
+                    _ => crop.right = v,
+                }
+                self.crop = Some(crop);
+            }
+            SidecarField::TerritoryNumber => {
+                self.territory_number = Some(value.to_string()).filter(|v| !v.is_empty());
+            }
+            SidecarField::ZoneName => {
+                self.zone_name = Some(value.to_string()).filter(|v| !v.is_empty());
+            }
+        }
+        Ok(())
+    }
+}
+
 impl AppConfig {
+    // Find the zone whose name matches the one parsed from a map filename
+    pub fn find_zone(&self, zone_name: &str) -> Option<&ZoneConfig> {
+        self.zones
+            .iter()
+            .find(|zone| zone.name.eq_ignore_ascii_case(zone_name))
+    }
+
+    // Copy of the configuration with every layout size multiplied by `factor`,
+    // used to render supersampled layouts. Map crops stay in screenshot pixels
+    pub fn scaled(&self, factor: u32) -> AppConfig {
+        let f = factor as f32;
+        let mut scaled = self.clone();
+        scaled.font.size_title *= f;
+        scaled.font.size_subtitle *= f;
+
+        let layout = &mut scaled.layout;
+        layout.width *= factor;
+        layout.height *= factor;
+        layout.margin *= factor;
+        layout.title_margin *= factor;
+        if let Some(legend) = &mut layout.legend {
+            legend.font_size *= f;
+        }
+        if let Some(qr_code) = &mut layout.qr_code {
+            qr_code.size *= factor;
+            qr_code.x *= factor;
+            qr_code.y *= factor;
+        }
+        for image in &mut layout.images {
+            image.x *= factor;
+            image.y *= factor;
+            image.width *= factor;
+            image.height *= factor;
+        }
+        if let Some(border) = &mut layout.border {
+            border.width *= factor;
+            border.inset *= factor;
+            border.radius *= factor;
+        }
+
+        let map = &mut scaled.map;
+        map.boundary_width *= factor;
+        if let Some(north_arrow) = &mut map.north_arrow {
+            north_arrow.size *= factor;
+        }
+        if let Some(scale_bar) = &mut map.scale_bar {
+            scale_bar.max_width *= factor;
+            scale_bar.font_size *= f;
+        }
+        if let Some(frame) = &mut map.frame {
+            frame.width *= factor;
+            frame.radius *= factor;
+            if let Some(shadow) = &mut frame.shadow {
+                shadow.offset *= factor as i32;
+                shadow.blur *= f;
+            }
+        }
+        if let Some(pages) = &mut map.pages {
+            pages.locator_size *= factor;
+        }
+        scaled
+    }
+
     pub fn load() -> Result<Self, config::ConfigError> {
         let settings = config::Config::builder()
             .add_source(config::File::with_name("config").required(false))
@@ -92,17 +666,56 @@ impl AppConfig {
                 text_title: String::from("Piantina di territorio"),
                 text_subtitle_left: String::from("Congregazione **Roma** Pratolungo"),
                 text_subtitle_right: String::from("**ZONA** <zone_name> **N.** <territory_number>"),
+                legend: None,
+                qr_code: None,
+                images: Vec::new(),
+                background: None,
+                border: None,
+                supersample: default_supersample(),
             },
             map: MapConfig {
                 maps_directory: String::from("./maps"),
+                zone_from_folder: false,
                 crop: MapCrop {
                     top: 100,
                     left: 50,
                     bottom: 77,
                     right: 82,
                 },
+                placement: MapPlacement::default(),
+                anchor: None,
+                resize_filter: ResizeFilter::default(),
+                rotation: 0.0,
+                auto_rotate: false,
+                boundary_width: default_boundary_width(),
+                meters_per_pixel: None,
+                north_arrow: None,
+                scale_bar: None,
+                frame: None,
+                pages: None,
+                enhance: None,
             },
             output_directory: String::from("layouts"),
+            output_format: OutputFormat::default(),
+            jpeg_quality: default_jpeg_quality(),
+            preview_graphics: TerminalGraphics::default(),
+            overview: None,
+            contact_sheet: None,
+            print: None,
+            report: None,
+            log: None,
+            zones: vec![
+                ZoneConfig {
+                    name: String::from("Casal Monastero"),
+                    color: String::from("#ed5858"),
+                    opacity: 0.4,
+                },
+                ZoneConfig {
+                    name: String::from("Torraccia"),
+                    color: String::from("#80ff80"),
+                    opacity: 0.35,
+                },
+            ],
         }
     }
 }
@@ -110,6 +723,8 @@ impl AppConfig {
 // Enum to represent all configurable fields in AppConfig
 pub enum ConfigField {
     OutputDirectory,
+    OutputFormat,
+    JpegQuality,
     FontPathRegular,
     FontPathBold,
     FontSizeTitle,
@@ -122,10 +737,21 @@ pub enum ConfigField {
     LayoutTextSubtitleLeft,
     LayoutTextSubtitleRight,
     MapDirectory,
+    MapZoneFromFolder,
     MapCropTop,
     MapCropLeft,
     MapCropBottom,
     MapCropRight,
+    MapPlacement,
+    MapAnchor,
+    MapResizeFilter,
+    LayoutSupersample,
+    PreviewGraphics,
+}
+
+// Parse a unit enum variant such as `Fit` or `BottomLeft` from its name
+fn parse_variant<T: DeserializeOwned>(value: &str) -> Option<T> {
+    T::deserialize(StrDeserializer::<serde::de::value::Error>::new(value)).ok()
 }
 
 // Implement FromStr for ConfigField to parse field names from strings
@@ -135,6 +761,8 @@ impl FromStr for ConfigField {
     fn from_str(input: &str) -> Result<ConfigField, Self::Err> {
         match input {
             "Output Directory" => Ok(ConfigField::OutputDirectory),
+            "Output Format" => Ok(ConfigField::OutputFormat),
+            "JPEG Quality" => Ok(ConfigField::JpegQuality),
             "Font - Regular Path" => Ok(ConfigField::FontPathRegular),
             "Font - Bold Path" => Ok(ConfigField::FontPathBold),
             "Font - Title Size" => Ok(ConfigField::FontSizeTitle),
@@ -147,10 +775,16 @@ impl FromStr for ConfigField {
             "Text Subtitle Left" => Ok(ConfigField::LayoutTextSubtitleLeft),
             "Text Subtitle Right" => Ok(ConfigField::LayoutTextSubtitleRight),
             "Maps Directory" => Ok(ConfigField::MapDirectory),
+            "Zone From Folder" => Ok(ConfigField::MapZoneFromFolder),
             "Map Crop - Top" => Ok(ConfigField::MapCropTop),
             "Map Crop - Left" => Ok(ConfigField::MapCropLeft),
             "Map Crop - Bottom" => Ok(ConfigField::MapCropBottom),
             "Map Crop - Right" => Ok(ConfigField::MapCropRight),
+            "Map Placement" => Ok(ConfigField::MapPlacement),
+            "Map Anchor" => Ok(ConfigField::MapAnchor),
+            "Map Resize Filter" => Ok(ConfigField::MapResizeFilter),
+            "Supersampling" => Ok(ConfigField::LayoutSupersample),
+            "Preview Graphics" => Ok(ConfigField::PreviewGraphics),
             _ => Err(()),
         }
     }
@@ -162,6 +796,8 @@ impl AppConfig {
     pub fn get_field_value(&self, field: &ConfigField) -> String {
         match field {
             ConfigField::OutputDirectory => self.output_directory.clone(),
+            ConfigField::OutputFormat => format!("{:?}", self.output_format),
+            ConfigField::JpegQuality => self.jpeg_quality.to_string(),
             ConfigField::FontPathRegular => self.font.path_regular.clone(),
             ConfigField::FontPathBold => self.font.path_bold.clone(),
             ConfigField::FontSizeTitle => self.font.size_title.to_string(),
@@ -174,10 +810,16 @@ impl AppConfig {
             ConfigField::LayoutTextSubtitleLeft => self.layout.text_subtitle_left.clone(),
             ConfigField::LayoutTextSubtitleRight => self.layout.text_subtitle_right.clone(),
             ConfigField::MapDirectory => self.map.maps_directory.clone(),
+            ConfigField::MapZoneFromFolder => self.map.zone_from_folder.to_string(),
             ConfigField::MapCropTop => self.map.crop.top.to_string(),
             ConfigField::MapCropLeft => self.map.crop.left.to_string(),
             ConfigField::MapCropBottom => self.map.crop.bottom.to_string(),
             ConfigField::MapCropRight => self.map.crop.right.to_string(),
+            ConfigField::MapPlacement => format!("{:?}", self.map.placement),
+            ConfigField::MapAnchor => format!("{:?}", self.map.anchor.unwrap_or_default()),
+            ConfigField::MapResizeFilter => format!("{:?}", self.map.resize_filter),
+            ConfigField::LayoutSupersample => self.layout.supersample.to_string(),
+            ConfigField::PreviewGraphics => format!("{:?}", self.preview_graphics),
         }
     }
 
@@ -185,6 +827,16 @@ impl AppConfig {
     pub fn set_field_value(&mut self, field: &ConfigField, value: String) {
         match field {
             ConfigField::OutputDirectory => self.output_directory = value,
+            ConfigField::OutputFormat => {
+                if let Some(v) = parse_variant(&value) {
+                    self.output_format = v;
+                }
+            }
+            ConfigField::JpegQuality => {
+                if let Ok(v) = value.parse::<u8>() {
+                    self.jpeg_quality = v.clamp(1, 100);
+                }
+            }
             ConfigField::FontPathRegular => self.font.path_regular = value,
             ConfigField::FontPathBold => self.font.path_bold = value,
             ConfigField::FontSizeTitle => {
@@ -221,6 +873,11 @@ impl AppConfig {
             ConfigField::LayoutTextSubtitleLeft => self.layout.text_subtitle_left = value,
             ConfigField::LayoutTextSubtitleRight => self.layout.text_subtitle_right = value,
             ConfigField::MapDirectory => self.map.maps_directory = value,
+            ConfigField::MapZoneFromFolder => {
+                if let Ok(v) = value.parse::<bool>() {
+                    self.map.zone_from_folder = v;
+                }
+            }
             ConfigField::MapCropTop => {
                 if let Ok(v) = value.parse::<u32>() {
                     self.map.crop.top = v;
@@ -241,6 +898,86 @@ impl AppConfig {
                     self.map.crop.right = v;
                 }
             }
+            ConfigField::MapPlacement => {
+                if let Some(v) = parse_variant(&value) {
+                    self.map.placement = v;
+                }
+            }
+            ConfigField::MapAnchor => {
+                if let Some(v) = parse_variant(&value) {
+                    self.map.anchor = Some(v);
+                }
+            }
+            ConfigField::MapResizeFilter => {
+                if let Some(v) = parse_variant(&value) {
+                    self.map.resize_filter = v;
+                }
+            }
+            ConfigField::LayoutSupersample => {
+                if let Ok(v) = value.parse::<u32>() {
+                    self.layout.supersample = v.clamp(1, 4);
+                }
+            }
+            ConfigField::PreviewGraphics => {
+                if let Some(v) = parse_variant(&value) {
+                    self.preview_graphics = v;
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_CROP: MapCrop = MapCrop {
+        top: 100,
+        left: 50,
+        bottom: 77,
+        right: 82,
+    };
+
+    #[test]
+    fn sets_a_crop_edge_from_the_default_crop() {
+        let mut sidecar = MapSidecar::default();
+        sidecar
+            .set_field_value(&SidecarField::CropLeft, " 12 ".to_string(), DEFAULT_CROP)
+            .unwrap();
+        let crop = sidecar.crop.unwrap();
+        assert_eq!(
+            (crop.top, crop.left, crop.bottom, crop.right),
+            (100, 12, 77, 82)
+        );
+        sidecar
+            .set_field_value(&SidecarField::CropTop, String::new(), DEFAULT_CROP)
+            .unwrap();
+        assert!(sidecar.crop.is_none());
+    }
+
+    #[test]
+    fn rejects_a_crop_that_is_not_a_number() {
+        let mut sidecar = MapSidecar::default();
+        let error = sidecar
+            .set_field_value(&SidecarField::CropTop, "12px".to_string(), DEFAULT_CROP)
+            .unwrap_err();
+        assert!(error.contains("12px"));
+        assert!(sidecar.crop.is_none());
+    }
+
+    #[test]
+    fn clears_names_with_an_empty_value() {
+        let mut sidecar = MapSidecar::default();
+        sidecar
+            .set_field_value(&SidecarField::ZoneName, "North".to_string(), DEFAULT_CROP)
+            .unwrap();
+        assert_eq!(
+            sidecar.get_field_value(&SidecarField::ZoneName).as_deref(),
+            Some("North")
+        );
+        sidecar
+            .set_field_value(&SidecarField::ZoneName, " ".to_string(), DEFAULT_CROP)
+            .unwrap();
+        assert_eq!(sidecar.get_field_value(&SidecarField::ZoneName), None);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::configuration::{Alignment, AppConfig, ContactSheetConfig};
+use crate::image_processing::{create_font_ref, load_font_data};
+use crate::text_processing::process_text;
+use ab_glyph::PxScale;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{draw_hollow_rect_mut, text_size};
+use imageproc::rect::Rect;
+use std::path::{Path, PathBuf};
+
+// Space around and between the thumbnails
+const PADDING: u32 = 20;
+
+// A small copy of a layout saved by the run, with the variables used in its caption
+pub struct SheetEntry {
+    pub thumbnail: RgbImage,
+    pub variables: Vec<(String, String)>,
+}
+
+// Shrink a finished layout to the contact sheet thumbnail width. Thumbnails are
+// taken before saving since not every output format can be read back, e.g. SVG
+pub fn sheet_thumbnail(layout: &RgbImage, sheet_config: &ContactSheetConfig) -> RgbImage {
+    let thumbnail_w = sheet_config.thumbnail_width.max(1);
+    let thumbnail_h = ((layout.height() as f32 * thumbnail_w as f32 / layout.width().max(1) as f32)
+        .round() as u32)
+        .max(1);
+    image::imageops::thumbnail(layout, thumbnail_w, thumbnail_h)
+}
+
+// Compose a grid with a thumbnail of every layout and its caption underneath,
+// to review a whole run at a glance before printing
+pub fn create_contact_sheet(
+    config: &AppConfig,
+    entries: &[SheetEntry],
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let sheet_config = match &config.contact_sheet {
+        Some(sheet_config) => sheet_config,
+        None => return Ok(None),
+    };
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    let font_data = load_font_data(&config.font.path_regular)?;
+    let font_regular = create_font_ref(&font_data)?;
+    let font_data = load_font_data(&config.font.path_bold)?;
+    let font_bold = create_font_ref(&font_data)?;
+    let scale = PxScale::from(sheet_config.font_size);
+
+    let thumbnail_w = sheet_config.thumbnail_width.max(1);
+    let columns = sheet_config.columns.clamp(1, entries.len() as u32);
+    let rows = (entries.len() as u32).div_ceil(columns);
+    let caption_h = sheet_config.font_size.ceil() as u32 + PADDING / 2;
+    let cell_w = thumbnail_w + PADDING;
+    let cell_h = entries
+        .iter()
+        .map(|e| e.thumbnail.height())
+        .max()
+        .unwrap_or(0)
+        + caption_h
+        + PADDING;
+
+    let mut sheet = RgbImage::from_pixel(
+        columns * cell_w + PADDING,
+        rows * cell_h + PADDING,
+        Rgb([255u8, 255u8, 255u8]),
+    );
+    for (index, entry) in entries.iter().enumerate() {
+        let thumbnail = &entry.thumbnail;
+        let x = PADDING + (index as u32 % columns) * cell_w;
+        let y = PADDING + (index as u32 / columns) * cell_h;
+        image::imageops::overlay(&mut sheet, thumbnail, x.into(), y.into());
+        draw_hollow_rect_mut(
+            &mut sheet,
+            Rect::at(x as i32, y as i32).of_size(thumbnail.width(), thumbnail.height()),
+            Rgb([160u8, 160u8, 160u8]),
+        );
+
+        let (label_w, _) = text_size(scale, &font_regular, &sheet_config.label);
+        process_text(
+            &sheet_config.label,
+            &entry.variables,
+            &font_regular,
+            &font_bold,
+            false,
+            scale,
+            &mut sheet,
+            None,
+            x + thumbnail_w.saturating_sub(label_w) / 2,
+            y + thumbnail.height() + PADDING / 2,
+            Alignment::Center,
+        )?;
+    }
+
+    let output_path = Path::new(&config.output_directory).join(&sheet_config.output_filename);
+    sheet.save(&output_path)?;
+    Ok(Some(output_path))
+}
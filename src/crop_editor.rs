@@ -0,0 +1,329 @@
+use crate::configuration::{AppConfig, MapCrop, MapSidecar};
+use crate::image_processing::{blend_color, draw_outline_rect, open_upright};
+use crate::process_images::{collect_maps, MapSource};
+use crate::terminal_image::{display_image, display_size, fit, terminal_cells};
+use crate::ui::{clear_terminal, display_header, pause_after_action, select_map};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    style::{Color, Stylize},
+};
+use image::{Rgb, RgbImage};
+use imageproc::drawing::draw_filled_rect_mut;
+use imageproc::rect::Rect;
+use std::path::Path;
+
+// Pixels an edge moves with one arrow key press, switched with + and -
+const STEPS: [u32; 4] = [1, 10, 50, 200];
+// Darkening of the parts of the screenshot that are cropped away
+const CROPPED_SHADE: f32 = 0.6;
+// Rows under the preview for the crop values and the keys
+const INFO_ROWS: u16 = 6;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edge {
+    Top,
+    Left,
+    Bottom,
+    Right,
+}
+
+impl Edge {
+    const ALL: [Edge; 4] = [Edge::Top, Edge::Left, Edge::Bottom, Edge::Right];
+
+    fn label(self) -> &'static str {
+        match self {
+            Edge::Top => "Top",
+            Edge::Left => "Left",
+            Edge::Bottom => "Bottom",
+            Edge::Right => "Right",
+        }
+    }
+
+    fn value(self, crop: &MapCrop) -> u32 {
+        match self {
+            Edge::Top => crop.top,
+            Edge::Left => crop.left,
+            Edge::Bottom => crop.bottom,
+            Edge::Right => crop.right,
+        }
+    }
+}
+
+// Move `edge` by `step` pixels towards the arrow that was pressed, keeping at least
+// one pixel of the map. Arrows across the edge leave it where it is
+fn nudge(crop: &mut MapCrop, edge: Edge, key: KeyCode, step: u32, width: u32, height: u32) {
+    let max_vertical = height.saturating_sub(crop.top + crop.bottom + 1);
+    let max_horizontal = width.saturating_sub(crop.left + crop.right + 1);
+    match (edge, key) {
+        (Edge::Top, KeyCode::Up) => crop.top = crop.top.saturating_sub(step),
+        (Edge::Top, KeyCode::Down) => crop.top += step.min(max_vertical),
+        (Edge::Bottom, KeyCode::Down) => crop.bottom = crop.bottom.saturating_sub(step),
+        (Edge::Bottom, KeyCode::Up) => crop.bottom += step.min(max_vertical),
+        (Edge::Left, KeyCode::Left) => crop.left = crop.left.saturating_sub(step),
+        (Edge::Left, KeyCode::Right) => crop.left += step.min(max_horizontal),
+        (Edge::Right, KeyCode::Right) => crop.right = crop.right.saturating_sub(step),
+        (Edge::Right, KeyCode::Left) => crop.right += step.min(max_horizontal),
+        _ => {}
+    }
+}
+
+// Size of the map left after cropping, if any
+fn cropped_size(crop: &MapCrop, width: u32, height: u32) -> Option<(u32, u32)> {
+    let w = width.checked_sub(crop.left + crop.right)?;
+    let h = height.checked_sub(crop.top + crop.bottom)?;
+    (w > 0 && h > 0).then_some((w, h))
+}
+
+// The part of `preview`, a scaled down copy of a `width` x `height` screenshot, that
+// the crop keeps. At least one pixel, so a tiny crop still shows on the preview
+fn preview_rect(crop: &MapCrop, width: u32, height: u32, preview: &RgbImage) -> Option<Rect> {
+    cropped_size(crop, width, height)?;
+    let (preview_w, preview_h) = preview.dimensions();
+    let to_x =
+        |x: u32| ((x as f32 * preview_w as f32 / width as f32).round() as u32).min(preview_w - 1);
+    let to_y =
+        |y: u32| ((y as f32 * preview_h as f32 / height as f32).round() as u32).min(preview_h - 1);
+    let (left, top) = (to_x(crop.left), to_y(crop.top));
+    let right = to_x(width - crop.right).max(left + 1).min(preview_w);
+    let bottom = to_y(height - crop.bottom).max(top + 1).min(preview_h);
+    Some(Rect::at(left as i32, top as i32).of_size(right - left, bottom - top))
+}
+
+// The preview of a `width` x `height` screenshot with the cropped parts darkened and
+// the selected edge highlighted
+fn draw_crop(preview: &RgbImage, width: u32, height: u32, crop: &MapCrop, edge: Edge) -> RgbImage {
+    let mut image = preview.clone();
+    let Some(rect) = preview_rect(crop, width, height, preview) else {
+        return image;
+    };
+    let (crop_w, crop_h) = (rect.width(), rect.height());
+    let inside = |x: u32, y: u32| {
+        let (x, y) = (x as i32, y as i32);
+        x >= rect.left() && x <= rect.right() && y >= rect.top() && y <= rect.bottom()
+    };
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if !inside(x, y) {
+            *pixel = blend_color(*pixel, Rgb([0, 0, 0]), CROPPED_SHADE);
+        }
+    }
+
+    draw_outline_rect(&mut image, rect, Rgb([255, 255, 0]), 1);
+    let thick = 3.min(crop_w).min(crop_h);
+    let highlight = match edge {
+        Edge::Top => Rect::at(rect.left(), rect.top()).of_size(crop_w, thick),
+        Edge::Bottom => {
+            Rect::at(rect.left(), rect.bottom() + 1 - thick as i32).of_size(crop_w, thick)
+        }
+        Edge::Left => Rect::at(rect.left(), rect.top()).of_size(thick, crop_h),
+        Edge::Right => Rect::at(rect.right() + 1 - thick as i32, rect.top()).of_size(thick, crop_h),
+    };
+    draw_filled_rect_mut(&mut image, highlight, Rgb([255, 0, 0]));
+    image
+}
+
+// Let the user move the crop edges over the first screenshot of `map`, and keep the
+// result as the crop of every map or as an override for this map only
+fn edit_crop(config: &mut AppConfig, map: &MapSource) -> Result<(), Box<dyn std::error::Error>> {
+    let tile = &map.tiles[0];
+    let screenshot = open_upright(tile)
+        .map_err(|e| format!("Failed to open map {} - {}", tile.display(), e))?
+        .to_rgb8();
+    let (width, height) = screenshot.dimensions();
+    // Shrunk to the terminal once, and again only when the terminal is resized
+    let mut preview: Option<((u32, u32), RgbImage)> = None;
+    let mut sidecar = MapSidecar::load(&map.path)?;
+    let initial_crop = sidecar.crop.unwrap_or(config.map.crop);
+    let mut crop = initial_crop;
+    let mut edge = Edge::Top;
+    let mut step_index = 1;
+    let mut message = String::new();
+
+    loop {
+        clear_terminal();
+        let (_, rows) = terminal_cells();
+        let max_rows = rows.saturating_sub(INFO_ROWS);
+        let size = display_size(config.preview_graphics, max_rows);
+        let preview = match &mut preview {
+            Some((preview_size, image)) if *preview_size == size => image,
+            preview => {
+                let image = fit(&screenshot, size.0, size.1);
+                &preview.insert((size, image)).1
+            }
+        };
+        display_image(
+            &draw_crop(preview, width, height, &crop, edge),
+            config.preview_graphics,
+            max_rows,
+        )?;
+
+        let edges: Vec<String> = Edge::ALL
+            .iter()
+            .map(|e| {
+                let text = format!("{}: {}", e.label(), e.value(&crop));
+                if *e == edge {
+                    format!("[{}]", text).with(Color::Cyan).to_string()
+                } else {
+                    format!(" {} ", text)
+                }
+            })
+            .collect();
+        let size = match cropped_size(&crop, width, height) {
+            Some((w, h)) => format!("{}x{}", w, h),
+            None => "nothing left".to_string(),
+        };
+        println!(
+            "\r{}  {}   Map: {} of {}x{}   Step: {}px",
+            map.name.display().to_string().with(Color::Cyan),
+            edges.join(" "),
+            size,
+            width,
+            height,
+            STEPS[step_index]
+        );
+        println!("\r{}", message.clone().with(Color::Yellow));
+        println!(
+            "\r{}",
+            "Tab: next edge   Arrows: move it   + -: step   g: use for all maps   \
+             m: use for this map   r: reset   Esc: back"
+                .with(Color::DarkGrey)
+        );
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            message.clear();
+            match key.code {
+                KeyCode::Tab => {
+                    let index = Edge::ALL.iter().position(|e| *e == edge).unwrap_or(0);
+                    edge = Edge::ALL[(index + 1) % Edge::ALL.len()];
+                }
+                KeyCode::BackTab => {
+                    let index = Edge::ALL.iter().position(|e| *e == edge).unwrap_or(0);
+                    edge = Edge::ALL[(index + Edge::ALL.len() - 1) % Edge::ALL.len()];
+                }
+                KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+                    nudge(&mut crop, edge, key.code, STEPS[step_index], width, height);
+                }
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    step_index = (step_index + 1).min(STEPS.len() - 1);
+                }
+                KeyCode::Char('-') => step_index = step_index.saturating_sub(1),
+                KeyCode::Char('r') => crop = initial_crop,
+                KeyCode::Char('g') => {
+                    config.map.crop = crop;
+                    message =
+                        "Crop set for all maps, use \"Save configurations\" to keep it".to_string();
+                    if sidecar.crop.is_some() {
+                        message += &format!(
+                            ". This map keeps its own crop from {}",
+                            MapSidecar::path_for(&map.path).display()
+                        );
+                    }
+                }
+                KeyCode::Char('m') => {
+                    sidecar.crop = Some(crop);
+                    sidecar.save(&map.path)?;
+                    message = format!(
+                        "Crop saved to {}",
+                        MapSidecar::path_for(&map.path).display()
+                    );
+                }
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Pick a map and adjust the crop over its screenshot
+pub fn crop_editor(config: &mut AppConfig) {
+    let maps = match collect_maps(Path::new(&config.map.maps_directory)) {
+        Ok(maps) if !maps.is_empty() => maps,
+        Ok(_) => {
+            pause_after_action("No maps found.\n\rPress Enter to return to the menu...");
+            return;
+        }
+        Err(e) => {
+            pause_after_action(&format!(
+                "{}\n\r{}\n\n\r{:#?}",
+                "An error occurred reading the maps :(.", "Press Enter to return to the menu...", e
+            ));
+            return;
+        }
+    };
+
+    while let Some(index) = select_map(&maps, "Adjust the map crop") {
+        if let Err(e) = edit_crop(config, &maps[index]) {
+            clear_terminal();
+            display_header();
+            pause_after_action(&format!(
+                "{}\n\r{}\n\n\r{:#?}",
+                "An error occurred editing the crop :(.", "Press Enter to pick another map...", e
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crop(top: u32, left: u32, bottom: u32, right: u32) -> MapCrop {
+        MapCrop {
+            top,
+            left,
+            bottom,
+            right,
+        }
+    }
+
+    fn edges(crop: &MapCrop) -> [u32; 4] {
+        Edge::ALL.map(|edge| edge.value(crop))
+    }
+
+    #[test]
+    fn nudges_the_selected_edge() {
+        let mut map_crop = crop(10, 10, 10, 10);
+        nudge(&mut map_crop, Edge::Top, KeyCode::Down, 5, 100, 100);
+        nudge(&mut map_crop, Edge::Right, KeyCode::Left, 5, 100, 100);
+        assert_eq!(edges(&map_crop), [15, 10, 10, 15]);
+        // Arrows across the edge don't move it
+        nudge(&mut map_crop, Edge::Top, KeyCode::Left, 5, 100, 100);
+        assert_eq!(edges(&map_crop), [15, 10, 10, 15]);
+    }
+
+    #[test]
+    fn nudges_within_the_screenshot() {
+        let mut map_crop = crop(3, 0, 0, 0);
+        nudge(&mut map_crop, Edge::Top, KeyCode::Up, 10, 100, 100);
+        assert_eq!(map_crop.top, 0);
+        nudge(&mut map_crop, Edge::Bottom, KeyCode::Up, 200, 100, 100);
+        assert_eq!(map_crop.bottom, 99);
+        assert_eq!(cropped_size(&map_crop, 100, 100), Some((100, 1)));
+    }
+
+    #[test]
+    fn cropped_size_needs_a_pixel_left() {
+        assert_eq!(
+            cropped_size(&crop(100, 50, 77, 82), 1170, 2532),
+            Some((1038, 2355))
+        );
+        assert_eq!(cropped_size(&crop(50, 0, 50, 0), 100, 100), None);
+        assert_eq!(cropped_size(&crop(0, 80, 0, 80), 100, 100), None);
+    }
+
+    #[test]
+    fn scales_the_crop_to_the_preview() {
+        let preview = RgbImage::new(50, 100);
+        let rect = preview_rect(&crop(100, 50, 300, 150), 500, 1000, &preview).unwrap();
+        assert_eq!(
+            (rect.left(), rect.top(), rect.width(), rect.height()),
+            (5, 10, 30, 60)
+        );
+        // A crop thinner than a preview pixel still shows
+        let rect = preview_rect(&crop(0, 0, 0, 499), 500, 1000, &preview).unwrap();
+        assert_eq!(rect.width(), 1);
+        assert!(preview_rect(&crop(600, 0, 400, 0), 500, 1000, &preview).is_none());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::configuration::{AppConfig, BorderConfig, FrameConfig, ShadowConfig};
+use crate::image_processing::{overlay_image, parse_hex_color};
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use imageproc::filter::gaussian_blur_f32;
+use imageproc::rect::Rect;
+
+// Whether the pixel at (x, y) lies inside a `w`x`h` rectangle with rounded corners
+fn inside_rounded_rect(x: i64, y: i64, w: i64, h: i64, radius: i64) -> bool {
+    if x < 0 || y < 0 || x >= w || y >= h {
+        return false;
+    }
+    let radius = radius.min(w / 2).min(h / 2);
+    let corner_x = if x < radius {
+        radius
+    } else if x >= w - radius {
+        w - radius - 1
+    } else {
+        return true;
+    };
+    let corner_y = if y < radius {
+        radius
+    } else if y >= h - radius {
+        h - radius - 1
+    } else {
+        return true;
+    };
+    let (dx, dy) = (x - corner_x, y - corner_y);
+    dx * dx + dy * dy <= radius * radius
+}
+
+// Create the layout canvas with the configured background color or image
+pub fn create_background(config: &AppConfig) -> Result<RgbImage, Box<dyn std::error::Error>> {
+    let (width, height) = (config.layout.width, config.layout.height);
+    let background = match &config.layout.background {
+        Some(background) => background,
+        None => {
+            return Ok(RgbImage::from_pixel(
+                width,
+                height,
+                Rgb([255u8, 255u8, 255u8]),
+            ))
+        }
+    };
+
+    let color = match &background.color {
+        Some(color) => parse_hex_color(color)?,
+        None => Rgb([255u8, 255u8, 255u8]),
+    };
+    let mut layout = RgbImage::from_pixel(width, height, color);
+
+    if let Some(path) = &background.image {
+        let image = image::open(path)
+            .map_err(|e| format!("Failed to open background image {} - {}", path, e))?;
+        // Cover the whole layout, cropping whatever does not fit
+        let image = image
+            .resize_to_fill(width, height, image::imageops::FilterType::Lanczos3)
+            .to_rgba8();
+        overlay_image(&mut layout, &image, 0, 0);
+    }
+    Ok(layout)
+}
+
+// Draw a border along the edges of the layout
+pub fn draw_border(
+    layout: &mut RgbImage,
+    border: &BorderConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let color = parse_hex_color(&border.color)?;
+    let (width, height) = (layout.width() as i64, layout.height() as i64);
+    let inset = border.inset as i64;
+    let outer_w = width - 2 * inset;
+    let outer_h = height - 2 * inset;
+    let thickness = border.width as i64;
+    let radius = border.radius as i64;
+
+    for y in 0..height {
+        for x in 0..width {
+            let (outer_x, outer_y) = (x - inset, y - inset);
+            let in_outer = inside_rounded_rect(outer_x, outer_y, outer_w, outer_h, radius);
+            let in_inner = inside_rounded_rect(
+                outer_x - thickness,
+                outer_y - thickness,
+                outer_w - 2 * thickness,
+                outer_h - 2 * thickness,
+                (radius - thickness).max(0),
+            );
+            if in_outer && !in_inner {
+                layout.put_pixel(x as u32, y as u32, color);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Make the map corners transparent outside the frame's rounded corners
+pub fn round_corners(map: &mut RgbaImage, radius: u32) {
+    if radius == 0 {
+        return;
+    }
+    let (width, height) = (map.width() as i64, map.height() as i64);
+    for (x, y, pixel) in map.enumerate_pixels_mut() {
+        if !inside_rounded_rect(x as i64, y as i64, width, height, radius as i64) {
+            pixel[3] = 0;
+        }
+    }
+}
+
+// Fill the part of `rect` inside its rounded corners, leaving the corners as they are
+pub fn fill_rounded_rect(layout: &mut RgbImage, rect: Rect, radius: u32, color: Rgb<u8>) {
+    let (w, h) = (rect.width() as i64, rect.height() as i64);
+    for y in 0..h {
+        for x in 0..w {
+            let (layout_x, layout_y) = (rect.left() as i64 + x, rect.top() as i64 + y);
+            if layout_x < 0
+                || layout_y < 0
+                || layout_x >= layout.width() as i64
+                || layout_y >= layout.height() as i64
+            {
+                continue;
+            }
+            if inside_rounded_rect(x, y, w, h, radius as i64) {
+                layout.put_pixel(layout_x as u32, layout_y as u32, color);
+            }
+        }
+    }
+}
+
+// Draw a blurred shadow behind the map, offset towards the bottom right
+pub fn draw_drop_shadow(
+    layout: &mut RgbImage,
+    map_rect: Rect,
+    frame: &FrameConfig,
+    shadow: &ShadowConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let color = parse_hex_color(&shadow.color)?;
+    let spread = (shadow.blur * 3.0).ceil() as u32;
+    let (shape_w, shape_h) = (
+        map_rect.width() + 2 * frame.width,
+        map_rect.height() + 2 * frame.width,
+    );
+    let radius = (frame.radius + frame.width) as i64;
+
+    let mut shadow_image = RgbaImage::from_pixel(
+        shape_w + 2 * spread,
+        shape_h + 2 * spread,
+        Rgba([color[0], color[1], color[2], 0]),
+    );
+    let alpha = (shadow.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+    for (x, y, pixel) in shadow_image.enumerate_pixels_mut() {
+        let (shape_x, shape_y) = (x as i64 - spread as i64, y as i64 - spread as i64);
+        if inside_rounded_rect(shape_x, shape_y, shape_w as i64, shape_h as i64, radius) {
+            pixel[3] = alpha;
+        }
+    }
+    if shadow.blur > 0.0 {
+        shadow_image = gaussian_blur_f32(&shadow_image, shadow.blur);
+    }
+
+    let x = map_rect.left() as i64 - frame.width as i64 - spread as i64 + shadow.offset as i64;
+    let y = map_rect.top() as i64 - frame.width as i64 - spread as i64 + shadow.offset as i64;
+    overlay_image(layout, &shadow_image, x, y);
+    Ok(())
+}
+
+// Draw the frame around the map, outside of the map area
+pub fn draw_map_frame(
+    layout: &mut RgbImage,
+    map_rect: Rect,
+    frame: &FrameConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if frame.width == 0 {
+        return Ok(());
+    }
+    let color = parse_hex_color(&frame.color)?;
+    let thickness = frame.width as i64;
+    let (left, top) = (map_rect.left() as i64, map_rect.top() as i64);
+    let (map_w, map_h) = (map_rect.width() as i64, map_rect.height() as i64);
+    let (outer_w, outer_h) = (map_w + 2 * thickness, map_h + 2 * thickness);
+    let radius = frame.radius as i64;
+
+    for outer_y in 0..outer_h {
+        for outer_x in 0..outer_w {
+            let (x, y) = (left - thickness + outer_x, top - thickness + outer_y);
+            if x < 0 || y < 0 || x >= layout.width() as i64 || y >= layout.height() as i64 {
+                continue;
+            }
+            let in_outer =
+                inside_rounded_rect(outer_x, outer_y, outer_w, outer_h, radius + thickness);
+            let in_map = inside_rounded_rect(x - left, y - top, map_w, map_h, radius);
+            if in_outer && !in_map {
+                layout.put_pixel(x as u32, y as u32, color);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounded_rect_excludes_only_the_corners() {
+        assert!(inside_rounded_rect(5, 5, 100, 50, 0));
+        assert!(inside_rounded_rect(0, 0, 100, 50, 0));
+        assert!(!inside_rounded_rect(0, 0, 100, 50, 10));
+        assert!(!inside_rounded_rect(99, 49, 100, 50, 10));
+        assert!(inside_rounded_rect(10, 0, 100, 50, 10));
+        assert!(inside_rounded_rect(0, 25, 100, 50, 10));
+        assert!(!inside_rounded_rect(100, 25, 100, 50, 10));
+    }
+
+    #[test]
+    fn rounded_fill_leaves_the_corners_untouched() {
+        let gray = Rgb([128, 128, 128]);
+        let white = Rgb([255, 255, 255]);
+        let mut layout = RgbImage::from_pixel(40, 40, gray);
+        fill_rounded_rect(&mut layout, Rect::at(5, 5).of_size(30, 30), 8, white);
+        assert_eq!(*layout.get_pixel(5, 5), gray);
+        assert_eq!(*layout.get_pixel(34, 34), gray);
+        assert_eq!(*layout.get_pixel(20, 20), white);
+        assert_eq!(*layout.get_pixel(20, 5), white);
+        assert_eq!(*layout.get_pixel(2, 2), gray);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::configuration::{AppConfig, EnhanceConfig, MapSidecar};
+use image::RgbaImage;
+
+// Rec. 601 luma of an RGB color
+fn luma(r: f32, g: f32, b: f32) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+// Move each color away from or towards its gray value
+fn adjust_saturation(map: &mut RgbaImage, saturation: f32) {
+    for pixel in map.pixels_mut() {
+        let [r, g, b, _] = pixel.0.map(|c| c as f32);
+        let gray = luma(r, g, b);
+        for channel in 0..3 {
+            let value = gray + (pixel[channel] as f32 - gray) * saturation;
+            pixel[channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+// Spread the colors away from mid gray by `contrast` percent. Unlike
+// `image::imageops::contrast` this leaves the transparency alone
+fn adjust_contrast(map: &mut RgbaImage, contrast: f32) {
+    let factor = ((100.0 + contrast) / 100.0).powi(2);
+    for pixel in map.pixels_mut() {
+        for channel in 0..3 {
+            let value = ((pixel[channel] as f32 / 255.0 - 0.5) * factor + 0.5) * 255.0;
+            pixel[channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+fn adjust_gamma(map: &mut RgbaImage, gamma: f32) {
+    let table: Vec<u8> = (0..=255)
+        .map(|value| {
+            ((value as f32 / 255.0).powf(1.0 / gamma) * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+        .collect();
+    for pixel in map.pixels_mut() {
+        for channel in 0..3 {
+            pixel[channel] = table[pixel[channel] as usize];
+        }
+    }
+}
+
+fn to_grayscale(map: &mut RgbaImage) {
+    for pixel in map.pixels_mut() {
+        let [r, g, b, _] = pixel.0.map(|c| c as f32);
+        let gray = luma(r, g, b).round() as u8;
+        pixel[0] = gray;
+        pixel[1] = gray;
+        pixel[2] = gray;
+    }
+}
+
+// Unsharp mask on the color channels, keeping the transparency as it was
+fn sharpen(map: &RgbaImage, sigma: f32, threshold: i32) -> RgbaImage {
+    let mut sharpened = image::imageops::unsharpen(map, sigma, threshold);
+    for (pixel, original) in sharpened.pixels_mut().zip(map.pixels()) {
+        pixel[3] = original[3];
+    }
+    sharpened
+}
+
+// Settings for one map: the configured ones with the sidecar's on top
+pub fn enhance_settings(config: &AppConfig, sidecar: &MapSidecar) -> Option<EnhanceConfig> {
+    match (&config.map.enhance, &sidecar.enhance) {
+        (Some(enhance), Some(overrides)) => Some(enhance.merge(overrides)),
+        (Some(enhance), None) => Some(enhance.clone()),
+        (None, Some(overrides)) => Some(overrides.clone()),
+        (None, None) => None,
+    }
+}
+
+// Apply the adjustments that are set, in a fixed order: tones first, then
+// colors, then sharpening
+pub fn enhance_map(map: &RgbaImage, enhance: &EnhanceConfig) -> RgbaImage {
+    let mut enhanced = map.clone();
+    if let Some(brightness) = enhance.brightness {
+        image::imageops::colorops::brighten_in_place(&mut enhanced, brightness);
+    }
+    if let Some(contrast) = enhance.contrast {
+        adjust_contrast(&mut enhanced, contrast);
+    }
+    if let Some(gamma) = enhance.gamma.filter(|gamma| *gamma > 0.0) {
+        adjust_gamma(&mut enhanced, gamma);
+    }
+    if let Some(saturation) = enhance.saturation {
+        adjust_saturation(&mut enhanced, saturation.max(0.0));
+    }
+    if enhance.grayscale == Some(true) {
+        to_grayscale(&mut enhanced);
+    }
+    if let Some(sigma) = enhance.sharpen.filter(|sigma| *sigma > 0.0) {
+        enhanced = sharpen(&enhanced, sigma, enhance.sharpen_threshold.unwrap_or(0));
+    }
+    enhanced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn enhance(map: &RgbaImage, settings: EnhanceConfig) -> RgbaImage {
+        enhance_map(map, &settings)
+    }
+
+    #[test]
+    fn brightness_clamps_at_white() {
+        let map = RgbaImage::from_pixel(2, 2, Rgba([250, 100, 0, 128]));
+        let settings = EnhanceConfig {
+            brightness: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(
+            *enhance(&map, settings).get_pixel(0, 0),
+            Rgba([255, 120, 20, 128])
+        );
+    }
+
+    #[test]
+    fn contrast_spreads_colors_from_mid_gray() {
+        let mut map = RgbaImage::from_pixel(3, 1, Rgba([128, 128, 128, 255]));
+        map.put_pixel(1, 0, Rgba([200, 200, 200, 255]));
+        map.put_pixel(2, 0, Rgba([50, 50, 50, 0]));
+        let settings = EnhanceConfig {
+            contrast: Some(50.0),
+            ..Default::default()
+        };
+        let enhanced = enhance(&map, settings);
+        assert!(enhanced.get_pixel(0, 0)[0].abs_diff(128) <= 1);
+        assert_eq!(enhanced.get_pixel(1, 0)[0], 255);
+        // Darker, with the transparency left alone
+        assert_eq!(*enhanced.get_pixel(2, 0), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn grayscale_uses_luma() {
+        let map = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        let settings = EnhanceConfig {
+            grayscale: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            *enhance(&map, settings).get_pixel(0, 0),
+            Rgba([76, 76, 76, 255])
+        );
+    }
+
+    #[test]
+    fn sharpen_threshold_leaves_small_steps_alone() {
+        // A faint step from 100 to 104 and a strong one from 104 to 200
+        let map = RgbaImage::from_fn(30, 10, |x, _| match x {
+            0..10 => Rgba([100, 100, 100, 255]),
+            10..20 => Rgba([104, 104, 104, 255]),
+            _ => Rgba([200, 200, 200, 255]),
+        });
+        let settings = EnhanceConfig {
+            sharpen: Some(1.0),
+            sharpen_threshold: Some(10),
+            ..Default::default()
+        };
+        let enhanced = enhance(&map, settings);
+        for x in 0..15 {
+            assert_eq!(enhanced.get_pixel(x, 5), map.get_pixel(x, 5));
+        }
+        assert!(enhanced.get_pixel(20, 5)[0] > 200);
+        assert!(enhanced.get_pixel(19, 5)[0] < 104);
+    }
+}
@@ -1,23 +1,288 @@
-use crate::configuration::{Alignment, AppConfig, MapCrop};
-use crate::text_processing::process_text;
+use crate::configuration::{Alignment, Anchor, AppConfig, MapCrop, MapPlacement, MapSidecar};
+use crate::decorations::{
+    create_background, draw_border, draw_drop_shadow, draw_map_frame, fill_rounded_rect,
+    round_corners,
+};
+use crate::enhance::{enhance_map, enhance_settings};
+use crate::map_overlays::{draw_north_arrow, draw_scale_bar};
+use crate::stitching::stitch_tiles;
+use crate::text_processing::{process_text, TextSpan};
 use ab_glyph::{FontRef, PxScale};
-use image::{ImageBuffer, Rgb, RgbImage};
-use imageproc::drawing::text_size;
+use image::{DynamicImage, ImageDecoder, ImageReader, Rgb, RgbImage, Rgba, RgbaImage};
+use imageproc::drawing::{draw_hollow_rect_mut, text_size};
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+use imageproc::rect::Rect;
 use std::fs;
+use std::path::{Path, PathBuf};
 
-fn load_font_data(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+pub fn load_font_data(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     fs::read(path).map_err(|e| format!("Failed to read font file {} - {}", path, e).into())
 }
 
-fn create_font_ref<'a>(font_data: &'a [u8]) -> Result<FontRef<'a>, Box<dyn std::error::Error>> {
+// Open an image in any format the `image` crate can decode, judged by its content
+// rather than its extension, and turn it upright following its EXIF orientation
+// as set by phone cameras
+pub fn open_upright(path: &Path) -> image::ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+pub fn create_font_ref<'a>(font_data: &'a [u8]) -> Result<FontRef<'a>, Box<dyn std::error::Error>> {
     FontRef::try_from_slice(font_data)
         .map_err(|e| format!("Failed to create font reference - {}", e).into())
 }
 
+// Parse a `#rrggbb` hex string into an RGB color
+pub fn parse_hex_color(hex: &str) -> Result<Rgb<u8>, Box<dyn std::error::Error>> {
+    let digits = hex.trim().trim_start_matches('#');
+    if digits.len() != 6 {
+        return Err(format!("Invalid color {} - expected #rrggbb", hex).into());
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&digits[i..i + 2], 16)
+            .map_err(|e| format!("Invalid color {} - {}", hex, e))
+    };
+    Ok(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+// Mix `color` over `base` with the given opacity (0.0 - 1.0)
+pub fn blend_color(base: Rgb<u8>, color: Rgb<u8>, opacity: f32) -> Rgb<u8> {
+    let opacity = opacity.clamp(0.0, 1.0);
+    let mix = |b: u8, c: u8| (b as f32 * (1.0 - opacity) + c as f32 * opacity).round() as u8;
+    Rgb([
+        mix(base[0], color[0]),
+        mix(base[1], color[1]),
+        mix(base[2], color[2]),
+    ])
+}
+
+// Composite an image with alpha over the layout at the given position
+pub fn overlay_image(layout: &mut RgbImage, image: &RgbaImage, x: i64, y: i64) {
+    for (image_x, image_y, pixel) in image.enumerate_pixels() {
+        let (layout_x, layout_y) = (x + image_x as i64, y + image_y as i64);
+        if layout_x < 0
+            || layout_y < 0
+            || layout_x >= layout.width() as i64
+            || layout_y >= layout.height() as i64
+        {
+            continue;
+        }
+        let base = layout.get_pixel_mut(layout_x as u32, layout_y as u32);
+        let color = Rgb([pixel[0], pixel[1], pixel[2]]);
+        *base = blend_color(*base, color, pixel[3] as f32 / 255.0);
+    }
+}
+
+// Rasterize an SVG file so that it fits in a `width`x`height` box
+fn load_svg(path: &Path, width: u32, height: u32) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    let data = fs::read(path)
+        .map_err(|e| format!("Failed to read image file {} - {}", path.display(), e))?;
+    let tree = resvg::usvg::Tree::from_data(&data, &resvg::usvg::Options::default())
+        .map_err(|e| format!("Failed to parse SVG {} - {}", path.display(), e))?;
+
+    let svg_size = tree.size();
+    let scale = f32::min(
+        width as f32 / svg_size.width(),
+        height as f32 / svg_size.height(),
+    );
+    let (svg_w, svg_h) = (
+        (svg_size.width() * scale).round().max(1.0) as u32,
+        (svg_size.height() * scale).round().max(1.0) as u32,
+    );
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(svg_w, svg_h)
+        .ok_or_else(|| format!("Invalid SVG size for {}", path.display()))?;
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia stores premultiplied alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(svg_w, svg_h, pixels)
+        .ok_or_else(|| format!("Failed to convert SVG {}", path.display()).into())
+}
+
+// Draw the configured static images, e.g. the congregation logo
+pub fn add_layout_images(
+    layout: &mut RgbImage,
+    config: &AppConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for element in &config.layout.images {
+        let path = Path::new(&element.path);
+        let is_svg = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        let image = if is_svg {
+            load_svg(path, element.width, element.height)?
+        } else {
+            let image = image::open(path)
+                .map_err(|e| format!("Failed to open image {} - {}", element.path, e))?;
+            image
+                .resize(
+                    element.width,
+                    element.height,
+                    image::imageops::FilterType::Lanczos3,
+                )
+                .to_rgba8()
+        };
+
+        // Center the image in its box when the aspect ratios differ
+        let x = element.x + (element.width.saturating_sub(image.width())) / 2;
+        let y = element.y + (element.height.saturating_sub(image.height())) / 2;
+        overlay_image(layout, &image, x.into(), y.into());
+    }
+    Ok(())
+}
+
+// Variables that can be used as `<name>` placeholders in layout texts
+pub fn text_variables(name: &str, number: &str) -> Vec<(String, String)> {
+    vec![
+        ("zone_name".to_string(), name.to_string()),
+        ("territory_number".to_string(), number.to_string()),
+    ]
+}
+
+// Multiplier for fixed paddings and line widths. When supersampling, layouts are
+// only drawn with the configuration from `AppConfig::scaled`, so this is the drawing scale
+pub fn pixel_scale(config: &AppConfig) -> u32 {
+    config.layout.supersample.clamp(1, 4)
+}
+
+// Outline a rectangle with lines `width` pixels thick, growing inwards
+pub fn draw_outline_rect(layout: &mut RgbImage, rect: Rect, color: Rgb<u8>, width: u32) {
+    for offset in 0..width.max(1) {
+        let (w, h) = (
+            rect.width().saturating_sub(2 * offset),
+            rect.height().saturating_sub(2 * offset),
+        );
+        if w == 0 || h == 0 {
+            break;
+        }
+        let inner = Rect::at(rect.left() + offset as i32, rect.top() + offset as i32).of_size(w, h);
+        draw_hollow_rect_mut(layout, inner, color);
+    }
+}
+
+// Bottom edge of the title and subtitle texts drawn by `create_layout`
+pub fn header_height(config: &AppConfig) -> u32 {
+    config.layout.margin + config.layout.title_margin + config.font.size_subtitle.ceil() as u32
+}
+
+// Area where the map is placed. It starts below the header once an anchor or a
+// placement other than `Fit` is set, since those can move the map up or grow it;
+// otherwise it keeps the original geometry of the whole layout inside the margins
+pub fn map_area(config: &AppConfig) -> Rect {
+    let margin = config.layout.margin;
+    let below_header =
+        config.map.anchor.is_some() || !matches!(config.map.placement, MapPlacement::Fit);
+    let top = if below_header {
+        header_height(config) + margin / 2
+    } else {
+        margin
+    };
+    Rect::at(margin as i32, top as i32).of_size(
+        config.layout.width.saturating_sub(2 * margin).max(1),
+        config.layout.height.saturating_sub(top + margin).max(1),
+    )
+}
+
+// Horizontal and vertical position of an anchor, from 0.0 (left/top) to 1.0 (right/bottom)
+fn anchor_fractions(anchor: Anchor) -> (f32, f32) {
+    match anchor {
+        Anchor::TopLeft => (0.0, 0.0),
+        Anchor::Top => (0.5, 0.0),
+        Anchor::TopRight => (1.0, 0.0),
+        Anchor::Left => (0.0, 0.5),
+        Anchor::Center => (0.5, 0.5),
+        Anchor::Right => (1.0, 0.5),
+        Anchor::BottomLeft => (0.0, 1.0),
+        Anchor::Bottom => (0.5, 1.0),
+        Anchor::BottomRight => (1.0, 1.0),
+    }
+}
+
+// Rotate clockwise by any angle, growing the image so no corner is cut off
+fn rotate_map(map: RgbaImage, degrees: f32) -> RgbaImage {
+    let degrees = degrees.rem_euclid(360.0);
+    if degrees == 0.0 {
+        return map;
+    } else if degrees == 90.0 {
+        return image::imageops::rotate90(&map);
+    } else if degrees == 180.0 {
+        return image::imageops::rotate180(&map);
+    } else if degrees == 270.0 {
+        return image::imageops::rotate270(&map);
+    }
+
+    let theta = degrees.to_radians();
+    let (width, height) = (map.width() as f32, map.height() as f32);
+    let rotated_w = (width * theta.cos().abs() + height * theta.sin().abs()).ceil() as u32;
+    let rotated_h = (width * theta.sin().abs() + height * theta.cos().abs()).ceil() as u32;
+
+    // Center the map on a transparent canvas big enough for both orientations
+    let canvas_w = rotated_w.max(map.width());
+    let canvas_h = rotated_h.max(map.height());
+    let mut canvas = RgbaImage::new(canvas_w, canvas_h);
+    image::imageops::replace(
+        &mut canvas,
+        &map,
+        ((canvas_w - map.width()) / 2).into(),
+        ((canvas_h - map.height()) / 2).into(),
+    );
+    let rotated = rotate_about_center(
+        &canvas,
+        theta,
+        Interpolation::Bicubic,
+        Rgba([0u8, 0u8, 0u8, 0u8]),
+    );
+    image::imageops::crop_imm(
+        &rotated,
+        (canvas_w - rotated_w) / 2,
+        (canvas_h - rotated_h) / 2,
+        rotated_w,
+        rotated_h,
+    )
+    .to_image()
+}
+
+// Pick the rotation, or the one turned by 90 degrees, that gives the larger fitted map
+fn best_rotation(map_w: u32, map_h: u32, area: Rect, degrees: f32) -> f32 {
+    let fitted_area = |w: f32, h: f32| {
+        let scale = f32::min(area.width() as f32 / w, area.height() as f32 / h);
+        w * h * scale * scale
+    };
+    let theta = degrees.to_radians();
+    let (w, h) = (map_w as f32, map_h as f32);
+    let (cos, sin) = (theta.cos().abs(), theta.sin().abs());
+    let upright = fitted_area(w * cos + h * sin, w * sin + h * cos);
+    let turned = fitted_area(w * sin + h * cos, w * cos + h * sin);
+    if turned > upright {
+        degrees + 90.0
+    } else {
+        degrees
+    }
+}
+
+// Draw the background, the title and subtitles and the border. The texts are added
+// to `spans` instead of being drawn when they are given
 pub fn create_layout(
     config: &AppConfig,
     name: &str,
     number: &str,
+    mut spans: Option<&mut Vec<TextSpan>>,
 ) -> Result<RgbImage, Box<dyn std::error::Error>> {
     let font_data = load_font_data(&config.font.path_regular)?;
     let font_regular = create_font_ref(&font_data)?;
@@ -31,14 +296,10 @@ pub fn create_layout(
     let text_subtitle_left = &config.layout.text_subtitle_left;
     let text_subtitle_right = &config.layout.text_subtitle_right;
 
-    let mut layout = ImageBuffer::from_pixel(
-        config.layout.width,
-        config.layout.height,
-        Rgb([255u8, 255u8, 255u8]),
-    );
+    let mut layout = create_background(config)?;
 
     let (title_w, _) = text_size(title_scale, &font_bold, text_title);
-    let (subtitle_right_w, _) = text_size(subtitle_scale, &font_regular, &text_subtitle_right);
+    let (subtitle_right_w, _) = text_size(subtitle_scale, &font_regular, text_subtitle_right);
 
     let title_x = (layout.width() - title_w) / 2;
     let subtitle_left_x = config.layout.margin;
@@ -47,18 +308,17 @@ pub fn create_layout(
     let title_y = config.layout.margin;
     let subtitle_y = title_y + config.layout.title_margin;
 
-    let variables = vec![
-        ("zone_name".to_string(), name.to_string()),
-        ("territory_number".to_string(), number.to_string()),
-    ];
+    let variables = text_variables(name, number);
 
     process_text(
         text_title,
         &variables,
         &font_regular,
         &font_bold,
+        false,
         title_scale,
         &mut layout,
+        spans.as_deref_mut(),
         title_x,
         title_y,
         Alignment::Center,
@@ -68,73 +328,270 @@ pub fn create_layout(
         &variables,
         &font_regular,
         &font_bold,
+        false,
         subtitle_scale,
         &mut layout,
+        spans.as_deref_mut(),
         subtitle_left_x,
         subtitle_y,
         Alignment::Left,
     )?;
     process_text(
-        &text_subtitle_right,
+        text_subtitle_right,
         &variables,
         &font_regular,
         &font_bold,
+        false,
         subtitle_scale,
         &mut layout,
+        spans,
         subtitle_right_x,
         subtitle_y,
         Alignment::Right,
     )?;
+
+    if let Some(border) = &config.layout.border {
+        draw_border(&mut layout, border)?;
+    }
     Ok(layout)
 }
 
-pub fn add_map_image(
-    layout: &mut RgbImage,
-    map_image_path: &str,
-    margin: u32,
+// Crop the screen elements that surround a map screenshot
+fn crop_map(
+    map_image: &RgbaImage,
     map_crop: MapCrop,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let map_image = image::open(map_image_path)?;
-    let map_image = map_image.to_rgb8();
-
+) -> Result<RgbaImage, Box<dyn std::error::Error>> {
     let (width, height) = map_image.dimensions();
     let (top, left, bottom, right) = (map_crop.top, map_crop.left, map_crop.bottom, map_crop.right);
     let crop_x = left;
     let crop_y = top;
-    let crop_width = width - right - left;
-    let crop_height = height - bottom - top;
+    let (crop_width, crop_height) = match (
+        width.checked_sub(right + left),
+        height.checked_sub(bottom + top),
+    ) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+        _ => {
+            return Err(
+                "Invalid crop dimensions, resulting width or height is zero or negative.".into(),
+            )
+        }
+    };
+
+    Ok(image::imageops::crop_imm(map_image, crop_x, crop_y, crop_width, crop_height).to_image())
+}
 
-    if crop_width <= 0 || crop_height <= 0 {
-        panic!("Invalid crop dimensions, resulting width or height is zero or negative.");
+// Cropped map ready to be placed, turned by `rotation` degrees clockwise
+pub struct MapImage {
+    pub image: RgbaImage,
+    pub rotation: f32,
+}
+
+impl MapImage {
+    // Part of the map, e.g. the piece shown on one page
+    pub fn crop(&self, rect: Rect) -> MapImage {
+        MapImage {
+            image: image::imageops::crop_imm(
+                &self.image,
+                rect.left() as u32,
+                rect.top() as u32,
+                rect.width(),
+                rect.height(),
+            )
+            .to_image(),
+            rotation: self.rotation,
+        }
     }
+}
 
-    let cropped_map =
-        image::imageops::crop_imm(&map_image, crop_x, crop_y, crop_width, crop_height).to_image();
+// Open, crop and rotate the screenshots of a territory, stitching them when there are several tiles
+pub fn load_map(
+    tiles: &[PathBuf],
+    config: &AppConfig,
+    sidecar: &MapSidecar,
+) -> Result<MapImage, Box<dyn std::error::Error>> {
+    let mut cropped_tiles = Vec::with_capacity(tiles.len());
+    for tile in tiles {
+        let map_image = open_upright(tile)
+            .map_err(|e| format!("Failed to open map {} - {}", tile.display(), e))?;
+        cropped_tiles.push(crop_map(
+            &map_image.to_rgba8(),
+            sidecar.crop.unwrap_or(config.map.crop),
+        )?);
+    }
 
-    let (target_w, target_h) = (layout.width() - 2 * margin, layout.height() - 2 * margin);
-    // Calculate the scaling factor to maintain aspect ratio
-    let scale_factor = f32::min(
-        target_w as f32 / cropped_map.width() as f32,
-        target_h as f32 / cropped_map.height() as f32,
-    );
+    let cropped_map = if cropped_tiles.len() == 1 {
+        cropped_tiles.remove(0)
+    } else {
+        stitch_tiles(&cropped_tiles, sidecar.tile_offsets.as_deref())?
+    };
 
-    // Compute the new dimensions that preserve the aspect ratio
-    let new_w = (cropped_map.width() as f32 * scale_factor).round() as u32;
-    let new_h = (cropped_map.height() as f32 * scale_factor).round() as u32;
+    let mut rotation = sidecar.rotation.unwrap_or(config.map.rotation);
+    if sidecar.auto_rotate.unwrap_or(config.map.auto_rotate) {
+        rotation = best_rotation(
+            cropped_map.width(),
+            cropped_map.height(),
+            map_area(config),
+            rotation,
+        );
+    }
+    Ok(MapImage {
+        image: rotate_map(cropped_map, rotation),
+        rotation,
+    })
+}
 
-    let resized_map = image::imageops::resize(
-        &cropped_map,
-        new_w,
-        new_h,
-        image::imageops::FilterType::Lanczos3,
-    );
+// Horizontal and vertical scaling factors of a map of the given size for the placement mode
+pub fn map_scale(config: &AppConfig, map_w: u32, map_h: u32) -> (f32, f32) {
+    let area = map_area(config);
+    let (target_w, target_h) = (area.width() as f32, area.height() as f32);
+    let (map_w, map_h) = (map_w as f32, map_h as f32);
+    match config.map.placement {
+        MapPlacement::Fit => {
+            let scale = f32::min(target_w / map_w, target_h / map_h);
+            (scale, scale)
+        }
+        MapPlacement::Fill => {
+            let scale = f32::max(target_w / map_w, target_h / map_h);
+            (scale, scale)
+        }
+        MapPlacement::Stretch => (target_w / map_w, target_h / map_h),
+        // Original size, enlarged only as much as the supersampled layout
+        MapPlacement::None => {
+            let scale = pixel_scale(config) as f32;
+            (scale, scale)
+        }
+    }
+}
 
-    let (overlay_x, overlay_y) = (
-        (margin + (target_w - new_w) / 2).into(),
-        (layout.height() - margin - new_h).into(),
-    );
+// Scale the map into the map area and draw it with its frame and overlays,
+// returning where it was placed on the layout
+pub fn add_map_image(
+    layout: &mut RgbImage,
+    map: &MapImage,
+    config: &AppConfig,
+    sidecar: &MapSidecar,
+    mut spans: Option<&mut Vec<TextSpan>>,
+) -> Result<Rect, Box<dyn std::error::Error>> {
+    let area = map_area(config);
+    // Adjust the tones and colors at the original resolution, before resizing
+    let enhanced_map;
+    let cropped_map = match enhance_settings(config, sidecar) {
+        Some(enhance) => {
+            enhanced_map = enhance_map(&map.image, &enhance);
+            &enhanced_map
+        }
+        None => &map.image,
+    };
+    let (target_w, target_h) = (area.width(), area.height());
+    let (map_w, map_h) = (cropped_map.width() as f32, cropped_map.height() as f32);
+    let (scale_x, scale_y) = map_scale(config, cropped_map.width(), cropped_map.height());
 
-    image::imageops::overlay(layout, &resized_map, overlay_x, overlay_y);
+    let new_w = ((map_w * scale_x).round() as u32).max(1);
+    let new_h = ((map_h * scale_y).round() as u32).max(1);
 
-    Ok(())
+    let resized_map = if (new_w, new_h) == cropped_map.dimensions() {
+        cropped_map.clone()
+    } else {
+        image::imageops::resize(
+            cropped_map,
+            new_w,
+            new_h,
+            config.map.resize_filter.filter_type(),
+        )
+    };
+
+    // Cut whatever overflows the map area, keeping the anchored side
+    let (anchor_x, anchor_y) = anchor_fractions(config.map.anchor.unwrap_or_default());
+    let (visible_w, visible_h) = (new_w.min(target_w), new_h.min(target_h));
+    let mut resized_map = image::imageops::crop_imm(
+        &resized_map,
+        ((new_w - visible_w) as f32 * anchor_x).round() as u32,
+        ((new_h - visible_h) as f32 * anchor_y).round() as u32,
+        visible_w,
+        visible_h,
+    )
+    .to_image();
+
+    let overlay_x = area.left() as i64 + ((target_w - visible_w) as f32 * anchor_x).round() as i64;
+    let overlay_y = area.top() as i64 + ((target_h - visible_h) as f32 * anchor_y).round() as i64;
+
+    let map_rect = Rect::at(overlay_x as i32, overlay_y as i32).of_size(visible_w, visible_h);
+    if let Some(frame) = &config.map.frame {
+        round_corners(&mut resized_map, frame.radius);
+        if let Some(shadow) = &frame.shadow {
+            draw_drop_shadow(layout, map_rect, frame, shadow)?;
+        }
+        // Transparent parts of the map, e.g. after a rotation, show white paper inside
+        // the frame, while the rounded corners show the background
+        fill_rounded_rect(layout, map_rect, frame.radius, Rgb([255u8, 255u8, 255u8]));
+    }
+
+    overlay_image(layout, &resized_map, overlay_x, overlay_y);
+
+    if let Some(frame) = &config.map.frame {
+        draw_map_frame(layout, map_rect, frame)?;
+    }
+    if let Some(north_arrow) = &config.map.north_arrow {
+        draw_north_arrow(
+            layout,
+            map_rect,
+            north_arrow,
+            config,
+            map.rotation,
+            spans.as_deref_mut(),
+        )?;
+    }
+    if let Some(scale_bar) = &config.map.scale_bar {
+        // Cropping keeps the source resolution, only the resize changes it
+        if let Some(meters_per_pixel) = sidecar.meters_per_pixel.or(config.map.meters_per_pixel) {
+            draw_scale_bar(
+                layout,
+                map_rect,
+                scale_bar,
+                config,
+                meters_per_pixel / scale_x,
+                spans,
+            )?;
+        }
+    }
+
+    Ok(map_rect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_hex_color("#ff8000").unwrap(), Rgb([255, 128, 0]));
+        assert_eq!(parse_hex_color(" 00aAfF ").unwrap(), Rgb([0, 170, 255]));
+        assert!(parse_hex_color("#fff").is_err());
+        assert!(parse_hex_color("#gg0000").is_err());
+    }
+
+    #[test]
+    fn map_area_keeps_the_original_geometry_without_an_anchor() {
+        let mut config = AppConfig::default();
+        let area = map_area(&config);
+        assert_eq!((area.left(), area.top()), (30, 30));
+        assert_eq!((area.width(), area.height()), (940, 647));
+
+        config.map.anchor = Some(Anchor::Center);
+        let top = (header_height(&config) + config.layout.margin / 2) as i32;
+        assert_eq!(map_area(&config).top(), top);
+
+        config.map.anchor = None;
+        config.map.placement = MapPlacement::Fill;
+        assert_eq!(map_area(&config).top(), top);
+    }
+
+    #[test]
+    fn blends_colors_by_opacity() {
+        let (black, white) = (Rgb([0, 0, 0]), Rgb([255, 255, 255]));
+        assert_eq!(blend_color(black, white, 0.0), black);
+        assert_eq!(blend_color(black, white, 1.0), white);
+        assert_eq!(blend_color(black, white, 0.5), Rgb([128, 128, 128]));
+        assert_eq!(blend_color(black, white, 2.0), white);
+    }
 }
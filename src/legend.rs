@@ -0,0 +1,158 @@
+use crate::configuration::{Alignment, AppConfig, Corner};
+use crate::image_processing::{
+    blend_color, create_font_ref, draw_outline_rect, header_height, load_font_data,
+    parse_hex_color, pixel_scale, text_variables,
+};
+use crate::text_processing::{process_text, TextSpan};
+use ab_glyph::PxScale;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_rect_mut, text_size};
+use imageproc::rect::Rect;
+
+const PADDING: u32 = 10;
+const WHITE: Rgb<u8> = Rgb([255u8, 255u8, 255u8]);
+const BLACK: Rgb<u8> = Rgb([0u8, 0u8, 0u8]);
+
+// What is drawn in the key column of a legend row
+enum LegendKey {
+    Swatch(Rgb<u8>, f32),
+    Line(Rgb<u8>),
+    Symbol(String),
+}
+
+pub fn add_legend(
+    layout: &mut RgbImage,
+    config: &AppConfig,
+    name: &str,
+    number: &str,
+    mut spans: Option<&mut Vec<TextSpan>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let legend = match &config.layout.legend {
+        Some(legend) => legend,
+        None => return Ok(()),
+    };
+
+    let font_data = load_font_data(&config.font.path_regular)?;
+    let font_regular = create_font_ref(&font_data)?;
+    let font_data = load_font_data(&config.font.path_bold)?;
+    let font_bold = create_font_ref(&font_data)?;
+
+    let scale = PxScale::from(legend.font_size);
+    let line_height = (legend.font_size * 1.5).ceil() as u32;
+    let key_size = legend.font_size.ceil() as u32;
+    let key_width = key_size * 2;
+    let padding = PADDING * pixel_scale(config);
+
+    // The boundary sample uses the color of the territory's own zone
+    let boundary_color = match config.find_zone(name) {
+        Some(zone) => parse_hex_color(&zone.color)?,
+        None => BLACK,
+    };
+
+    let mut rows = Vec::new();
+    for zone in &config.zones {
+        rows.push((
+            LegendKey::Swatch(parse_hex_color(&zone.color)?, zone.opacity),
+            zone.name.clone(),
+        ));
+    }
+    rows.push((
+        LegendKey::Line(boundary_color),
+        legend.boundary_label.clone(),
+    ));
+    for symbol in &legend.symbols {
+        rows.push((
+            LegendKey::Symbol(symbol.symbol.clone()),
+            symbol.label.clone(),
+        ));
+    }
+
+    let (title_w, _) = text_size(scale, &font_bold, &legend.title.replace("**", ""));
+    let rows_w = rows
+        .iter()
+        .map(|(_, label)| text_size(scale, &font_regular, &label.replace("**", "")).0)
+        .max()
+        .unwrap_or(0);
+    let box_w = u32::max(title_w, key_width + padding + rows_w) + 2 * padding;
+    let box_h = (rows.len() as u32 + 1) * line_height + 2 * padding;
+
+    let margin = config.layout.margin;
+    let box_x = match legend.corner {
+        Corner::TopLeft | Corner::BottomLeft => margin,
+        Corner::TopRight | Corner::BottomRight => layout.width().saturating_sub(margin + box_w),
+    };
+    let box_y = match legend.corner {
+        Corner::TopLeft | Corner::TopRight => header_height(config) + padding,
+        Corner::BottomLeft | Corner::BottomRight => layout.height().saturating_sub(margin + box_h),
+    };
+
+    let legend_rect = Rect::at(box_x as i32, box_y as i32).of_size(box_w, box_h);
+    draw_filled_rect_mut(layout, legend_rect, WHITE);
+    draw_outline_rect(layout, legend_rect, BLACK, pixel_scale(config));
+
+    let variables = text_variables(name, number);
+    let text_x = box_x + padding;
+    let mut text_y = box_y + padding;
+
+    process_text(
+        &legend.title,
+        &variables,
+        &font_regular,
+        &font_bold,
+        true,
+        scale,
+        layout,
+        spans.as_deref_mut(),
+        text_x,
+        text_y,
+        Alignment::Left,
+    )?;
+    text_y += line_height;
+
+    for (key, label) in &rows {
+        match key {
+            LegendKey::Swatch(color, opacity) => {
+                let swatch = Rect::at(text_x as i32, text_y as i32).of_size(key_width, key_size);
+                draw_filled_rect_mut(layout, swatch, blend_color(WHITE, *color, *opacity));
+                draw_outline_rect(layout, swatch, *color, pixel_scale(config));
+            }
+            LegendKey::Line(color) => {
+                let line_w = config.map.boundary_width.clamp(1, key_size);
+                let line_y = text_y + (key_size - line_w) / 2;
+                let line = Rect::at(text_x as i32, line_y as i32).of_size(key_width, line_w);
+                draw_filled_rect_mut(layout, line, *color);
+            }
+            LegendKey::Symbol(symbol) => {
+                process_text(
+                    symbol,
+                    &variables,
+                    &font_regular,
+                    &font_bold,
+                    false,
+                    scale,
+                    layout,
+                    spans.as_deref_mut(),
+                    text_x,
+                    text_y,
+                    Alignment::Left,
+                )?;
+            }
+        }
+        process_text(
+            label,
+            &variables,
+            &font_regular,
+            &font_bold,
+            false,
+            scale,
+            layout,
+            spans.as_deref_mut(),
+            text_x + key_width + padding,
+            text_y,
+            Alignment::Left,
+        )?;
+        text_y += line_height;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use crate::configuration::AppConfig;
+use crossterm::style::{Color, Stylize};
+use indicatif::ProgressBar;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Log messages of this crate, not of the libraries it uses
+const TARGET: &str = env!("CARGO_PKG_NAME");
+
+struct LogFile {
+    file: File,
+    level: LevelFilter,
+}
+
+// Sends each message to the terminal, above the progress bar when one is shown,
+// and to the log file when one is open, each with its own level
+struct Logger {
+    console_level: Mutex<LevelFilter>,
+    file: Mutex<Option<LogFile>>,
+    progress_bar: Mutex<Option<ProgressBar>>,
+}
+
+static LOGGER: Logger = Logger {
+    console_level: Mutex::new(LevelFilter::Warn),
+    file: Mutex::new(None),
+    progress_bar: Mutex::new(None),
+};
+
+// UTC date and time like `2024-05-01 18:30:12`, from the days-to-civil algorithm
+// of Howard Hinnant
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0);
+    format_timestamp(seconds)
+}
+
+// Date and time of `seconds` since the Unix epoch
+fn format_timestamp(seconds: i64) -> String {
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with(TARGET)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = record.level();
+
+        if let Some(log_file) = self.file.lock().unwrap().as_mut() {
+            if level <= log_file.level {
+                // A failing log file must not stop the processing
+                let _ = writeln!(
+                    log_file.file,
+                    "{} {:5} {}",
+                    timestamp(),
+                    level,
+                    record.args()
+                );
+            }
+        }
+
+        if level <= *self.console_level.lock().unwrap() {
+            let message = format!("{}", record.args());
+            let message = match level {
+                Level::Error => message.with(Color::Red),
+                Level::Warn => message.with(Color::Yellow),
+                Level::Info => message.with(Color::White),
+                Level::Debug | Level::Trace => message.with(Color::DarkGrey),
+            };
+            // The terminal may be in raw mode, so each line starts with a carriage return
+            let print = || eprintln!("\r{}", message);
+            match self.progress_bar.lock().unwrap().as_ref() {
+                Some(progress_bar) => progress_bar.suspend(print),
+                None => print(),
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(log_file) = self.file.lock().unwrap().as_mut() {
+            let _ = log_file.file.flush();
+        }
+    }
+}
+
+// Install the logger, showing messages up to `console_level` in the terminal
+pub fn init(console_level: LevelFilter) {
+    *LOGGER.console_level.lock().unwrap() = console_level;
+    // Only fails when a logger is already installed
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(LevelFilter::Debug);
+}
+
+// Append the messages to the configured log file in the output directory, or stop
+// writing to a log file when none is configured
+pub fn open_log_file(config: &AppConfig) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let Some(log) = &config.log else {
+        *LOGGER.file.lock().unwrap() = None;
+        return Ok(None);
+    };
+    let output_directory = Path::new(&config.output_directory);
+    fs::create_dir_all(output_directory)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    let path = output_directory.join(&log.output_filename);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open log file {} - {}", path.display(), e))?;
+    *LOGGER.file.lock().unwrap() = Some(LogFile {
+        file,
+        level: log.level.filter(),
+    });
+    Ok(Some(path))
+}
+
+// Print the messages above `progress_bar` while it is shown, so they don't break it
+pub fn set_progress_bar(progress_bar: Option<ProgressBar>) {
+    *LOGGER.progress_bar.lock().unwrap() = progress_bar;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_known_dates() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(1_714_588_212), "2024-05-01 18:30:12");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+    }
+
+    #[test]
+    fn formats_leap_days() {
+        // 2024-02-29 exists, 2023-02-29 doesn't and 1900 was no leap year
+        assert_eq!(format_timestamp(1_709_164_800), "2024-02-29 00:00:00");
+        assert_eq!(format_timestamp(1_677_628_800), "2023-03-01 00:00:00");
+        assert_eq!(format_timestamp(-2_203_977_600), "1900-02-28 00:00:00");
+        assert_eq!(format_timestamp(-2_203_891_200), "1900-03-01 00:00:00");
+    }
+
+    #[test]
+    fn formats_month_and_year_boundaries() {
+        assert_eq!(format_timestamp(1_706_745_599), "2024-01-31 23:59:59");
+        assert_eq!(format_timestamp(1_706_745_600), "2024-02-01 00:00:00");
+        assert_eq!(format_timestamp(1_704_067_199), "2023-12-31 23:59:59");
+        assert_eq!(format_timestamp(1_704_067_200), "2024-01-01 00:00:00");
+        assert_eq!(format_timestamp(-1), "1969-12-31 23:59:59");
+    }
+}
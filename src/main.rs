@@ -1,32 +1,113 @@
+mod build_cache;
 mod configuration;
+mod contact_sheet;
+mod crop_editor;
+mod decorations;
+mod enhance;
 mod image_processing;
+mod legend;
+mod logging;
+mod map_overlays;
+mod output;
+mod overview;
+mod pages;
+mod plan;
+mod print;
 mod process_images;
+mod qr_code;
+mod report;
+mod stitching;
+mod terminal_image;
 mod text_processing;
 mod ui;
+mod watch;
 
 use configuration::AppConfig;
+use crop_editor::crop_editor;
 use crossterm::{
     event::{self, Event, KeyCode, KeyEventKind},
     style::{Color, Stylize},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
+use log::{error, warn, LevelFilter};
+use overview::create_overview;
+use plan::print_plan;
 use process_images::process_images;
 use ui::{
     clear_terminal, display_config, display_goodbye, display_header, display_menu, edit_config,
-    pause_after_action,
+    pause_after_action, preview_territory, review_failures,
 };
+use watch::watch;
+
+const USAGE: &str = "Usage: buggy [watch | plan] [--force] [-v | -vv | -q]";
+
+// Command line options, e.g. `buggy --force`, `buggy watch` or `buggy plan`
+struct Options {
+    // Render every territory, even those the build cache finds up to date
+    force: bool,
+    // Re-render territories as their files change instead of showing the menu
+    watch: bool,
+    // Print what processing would do without writing anything
+    plan: bool,
+    // Most detailed messages shown in the terminal, when not the default of the mode
+    verbosity: Option<LevelFilter>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        force: false,
+        watch: false,
+        plan: false,
+        verbosity: None,
+    };
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--force" => options.force = true,
+            "watch" => options.watch = true,
+            "plan" => options.plan = true,
+            "-v" | "--verbose" => options.verbosity = Some(LevelFilter::Info),
+            "-vv" => options.verbosity = Some(LevelFilter::Debug),
+            "-q" | "--quiet" => options.verbosity = Some(LevelFilter::Error),
+            _ => return Err(format!("Unknown option {}\n{}", arg, USAGE)),
+        }
+    }
+    Ok(options)
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_options().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    });
+    // Watch mode reports each rendered territory, the menu only the problems
+    logging::init(options.verbosity.unwrap_or(if options.watch {
+        LevelFilter::Info
+    } else {
+        LevelFilter::Warn
+    }));
+    if options.watch {
+        let config = AppConfig::load()?;
+        return watch(config);
+    }
+    if options.plan {
+        let config = AppConfig::load()?;
+        return print_plan(&config, options.force);
+    }
+
     enable_raw_mode()?; // Enable raw mode to capture key events
     let mut config = AppConfig::load().unwrap_or_else(|err| {
-        eprintln!("\rError loading configuration: {}", err);
-        println!("\rUsing default configuration");
+        error!("Error loading configuration: {}", err);
+        warn!("Using default configuration");
         AppConfig::default()
     });
 
     let mut selected_option = 0;
     let menu_options = [
         "Process images and create layouts",
+        "Show processing plan (dry run)",
+        "Create overview map",
+        "Preview a territory layout",
+        "Adjust the map crop",
         "View current configurations",
         "Edit configurations",
         "Save configurations",
@@ -43,27 +124,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 continue;
             }
             match key_event.code {
-                KeyCode::Up => {
-                    if selected_option > 0 {
-                        selected_option -= 1;
-                    }
-                }
-                KeyCode::Down => {
-                    if selected_option < menu_options.len() - 1 {
-                        selected_option += 1;
-                    }
+                KeyCode::Up => selected_option = selected_option.saturating_sub(1),
+                KeyCode::Down if selected_option < menu_options.len() - 1 => {
+                    selected_option += 1;
                 }
                 KeyCode::Enter => match selected_option {
                     0 => {
                         clear_terminal();
                         display_header();
                         println!("\r{}", "Processing images...\n".with(Color::Yellow));
-                        match process_images(&config) {
-                            Ok(_) => {
+                        match process_images(&config, options.force) {
+                            Ok(failures) if failures.is_empty() => {
                                 pause_after_action(
                                     "Images processed.\n\rPress Enter to return to the menu...",
                                 );
                             }
+                            Ok(failures) => {
+                                pause_after_action(&format!(
+                                    "Images processed, {} failed.\n\rPress Enter to review them...",
+                                    failures.len()
+                                ));
+                                review_failures(&config, failures);
+                            }
                             Err(e) => {
                                 pause_after_action(&format!(
                                     "{}\n\r{}\n\n\r{:#?}",
@@ -75,17 +157,61 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         };
                     }
                     1 => {
+                        clear_terminal();
+                        display_header();
+                        println!(
+                            "\r{}",
+                            "Processing plan, nothing is written...\n".with(Color::Yellow)
+                        );
+                        match print_plan(&config, options.force) {
+                            Ok(_) => {
+                                pause_after_action("\n\rPress Enter to return to the menu...");
+                            }
+                            Err(e) => {
+                                pause_after_action(&format!(
+                                    "{}\n\r{}\n\n\r{:#?}",
+                                    "An error occurred planning the processing :(.",
+                                    "Press Enter to return to the menu...",
+                                    e
+                                ));
+                            }
+                        };
+                    }
+                    2 => {
+                        clear_terminal();
+                        display_header();
+                        println!("\r{}", "Creating overview map...\n".with(Color::Yellow));
+                        match create_overview(&config) {
+                            Ok(output_path) => {
+                                pause_after_action(&format!(
+                                    "Overview map saved to {}.\n\rPress Enter to return to the menu...",
+                                    output_path.display()
+                                ));
+                            }
+                            Err(e) => {
+                                pause_after_action(&format!(
+                                    "{}\n\r{}\n\n\r{:#?}",
+                                    "An error occurred creating the overview map :(.",
+                                    "Press Enter to return to the menu...",
+                                    e
+                                ));
+                            }
+                        };
+                    }
+                    3 => preview_territory(&config),
+                    4 => crop_editor(&mut config),
+                    5 => {
                         clear_terminal();
                         display_header();
                         display_config(&config);
                         pause_after_action("Press Enter to return to the menu...");
                     }
-                    2 => {
+                    6 => {
                         clear_terminal();
                         display_header();
                         edit_config(&mut config);
                     }
-                    3 => {
+                    7 => {
                         clear_terminal();
                         display_header();
                         config.save_config();
@@ -93,7 +219,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             "Configuration saved. Press Enter to return to the menu...",
                         );
                     }
-                    4 => {
+                    8 => {
                         display_goodbye();
                         disable_raw_mode()?; // Restore terminal mode
                         return Ok(());
@@ -0,0 +1,209 @@
+use crate::configuration::{Alignment, AppConfig, Corner, NorthArrowConfig, ScaleBarConfig};
+use crate::image_processing::{create_font_ref, draw_outline_rect, load_font_data, pixel_scale};
+use crate::text_processing::{process_text, TextSpan};
+use ab_glyph::PxScale;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{
+    draw_filled_rect_mut, draw_line_segment_mut, draw_polygon_mut, text_size,
+};
+use imageproc::point::Point;
+use imageproc::rect::Rect;
+
+const INSET: u32 = 15;
+const WHITE: Rgb<u8> = Rgb([255u8, 255u8, 255u8]);
+const BLACK: Rgb<u8> = Rgb([0u8, 0u8, 0u8]);
+const BAR_HEIGHT: u32 = 8;
+const BAR_SEGMENTS: u32 = 4;
+
+// Position of a `w`x`h` element in the given corner of the map area
+pub fn corner_position(
+    map_rect: Rect,
+    corner: Corner,
+    w: u32,
+    h: u32,
+    config: &AppConfig,
+) -> (i32, i32) {
+    let inset = INSET * pixel_scale(config);
+    let x = match corner {
+        Corner::TopLeft | Corner::BottomLeft => map_rect.left() + inset as i32,
+        Corner::TopRight | Corner::BottomRight => map_rect.right() + 1 - (inset + w) as i32,
+    };
+    let y = match corner {
+        Corner::TopLeft | Corner::TopRight => map_rect.top() + inset as i32,
+        Corner::BottomLeft | Corner::BottomRight => map_rect.bottom() + 1 - (inset + h) as i32,
+    };
+    (x, y)
+}
+
+// Round a length in meters down to 1, 2 or 5 times a power of ten
+fn nice_length(max_meters: f32) -> f32 {
+    let magnitude = 10f32.powf(max_meters.log10().floor());
+    [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|length| *length <= max_meters)
+        .unwrap_or(magnitude)
+}
+
+fn format_length(meters: f32) -> String {
+    if meters >= 1000.0 {
+        format!("{} km", meters / 1000.0)
+    } else {
+        format!("{} m", meters)
+    }
+}
+
+// Draw an arrow pointing north, turned by the same clockwise rotation as the map
+pub fn draw_north_arrow(
+    layout: &mut RgbImage,
+    map_rect: Rect,
+    arrow: &NorthArrowConfig,
+    config: &AppConfig,
+    rotation: f32,
+    spans: Option<&mut Vec<TextSpan>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let font_data = load_font_data(&config.font.path_bold)?;
+    let font_bold = create_font_ref(&font_data)?;
+    let size = arrow.size.max(8);
+    let label_size = size as f32 / 3.0;
+    let label_scale = PxScale::from(label_size);
+    let (label_w, _) = text_size(label_scale, &font_bold, "N");
+
+    // Reserve a square big enough for the arrow and its label in any direction
+    let box_size = size + 2 * label_size.ceil() as u32;
+    let (box_x, box_y) = corner_position(map_rect, arrow.corner, box_size, box_size, config);
+    let center = (
+        box_x as f32 + box_size as f32 / 2.0,
+        box_y as f32 + box_size as f32 / 2.0,
+    );
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    // Offsets are relative to the center, with y growing downwards
+    let rotate = |dx: f32, dy: f32| {
+        Point::new(
+            (center.0 + dx * cos - dy * sin).round() as i32,
+            (center.1 + dx * sin + dy * cos).round() as i32,
+        )
+    };
+
+    let (half_w, half_h) = (size as f32 / 4.0, size as f32 / 2.0);
+    let tip = rotate(0.0, -half_h);
+    let base_left = rotate(-half_w, half_h);
+    let base_right = rotate(half_w, half_h);
+    let notch = rotate(0.0, half_h / 2.0);
+
+    // Classic two-tone arrow: left half filled, right half outlined
+    draw_polygon_mut(layout, &[tip, base_left, notch], BLACK);
+    draw_polygon_mut(layout, &[tip, notch, base_right], WHITE);
+    let line_w = pixel_scale(config) as i32;
+    for (start, end) in [(tip, base_right), (base_right, notch), (tip, notch)] {
+        for dx in 0..line_w {
+            for dy in 0..line_w {
+                draw_line_segment_mut(
+                    layout,
+                    ((start.x + dx) as f32, (start.y + dy) as f32),
+                    ((end.x + dx) as f32, (end.y + dy) as f32),
+                    BLACK,
+                );
+            }
+        }
+    }
+
+    // The label stays upright just beyond the tip
+    let label_center = rotate(0.0, -half_h - label_size * 0.6);
+    process_text(
+        "**N**",
+        &[],
+        &font_bold,
+        &font_bold,
+        true,
+        label_scale,
+        layout,
+        spans,
+        (label_center.x - label_w as i32 / 2).max(0) as u32,
+        (label_center.y - (label_size / 2.0) as i32).max(0) as u32,
+        Alignment::Left,
+    )
+}
+
+pub fn draw_scale_bar(
+    layout: &mut RgbImage,
+    map_rect: Rect,
+    scale_bar: &ScaleBarConfig,
+    config: &AppConfig,
+    meters_per_pixel: f32,
+    spans: Option<&mut Vec<TextSpan>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if meters_per_pixel <= 0.0 {
+        return Err("Invalid scale, meters per pixel must be positive".into());
+    }
+
+    let font_data = load_font_data(&config.font.path_regular)?;
+    let font_regular = create_font_ref(&font_data)?;
+    let font_data = load_font_data(&config.font.path_bold)?;
+    let font_bold = create_font_ref(&font_data)?;
+    let scale = PxScale::from(scale_bar.font_size);
+
+    let length = nice_length(scale_bar.max_width as f32 * meters_per_pixel);
+    let bar_w = (length / meters_per_pixel).round() as u32;
+    let label = format_length(length);
+    let (label_w, _) = text_size(scale, &font_regular, &label);
+    let label_h = scale_bar.font_size.ceil() as u32;
+
+    let padding = 4 * pixel_scale(config);
+    let bar_h = BAR_HEIGHT * pixel_scale(config);
+    let box_w = bar_w + label_w + 3 * padding;
+    let box_h = u32::max(bar_h, label_h) + 2 * padding;
+    let (box_x, box_y) = corner_position(map_rect, scale_bar.corner, box_w, box_h, config);
+    draw_filled_rect_mut(layout, Rect::at(box_x, box_y).of_size(box_w, box_h), WHITE);
+
+    let bar_x = box_x + padding as i32;
+    let bar_y = box_y + (box_h - bar_h) as i32 / 2;
+    // Alternate filled and empty segments along the bar
+    let segment_w = (bar_w / BAR_SEGMENTS).max(1);
+    for segment in (0..BAR_SEGMENTS).step_by(2) {
+        let segment_x = bar_x + (segment * segment_w) as i32;
+        let rect = Rect::at(segment_x, bar_y).of_size(segment_w, bar_h);
+        draw_filled_rect_mut(layout, rect, BLACK);
+    }
+    draw_outline_rect(
+        layout,
+        Rect::at(bar_x, bar_y).of_size(bar_w.max(1), bar_h),
+        BLACK,
+        pixel_scale(config),
+    );
+
+    process_text(
+        &label,
+        &[],
+        &font_regular,
+        &font_bold,
+        false,
+        scale,
+        layout,
+        spans,
+        (bar_x + (bar_w + padding) as i32).max(0) as u32,
+        (box_y + (box_h - label_h) as i32 / 2).max(0) as u32,
+        Alignment::Left,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_lengths_down_to_nice_steps() {
+        assert_eq!(nice_length(1.0), 1.0);
+        assert_eq!(nice_length(180.0), 100.0);
+        assert_eq!(nice_length(240.0), 200.0);
+        assert_eq!(nice_length(730.0), 500.0);
+        assert_eq!(nice_length(4999.0), 2000.0);
+    }
+
+    #[test]
+    fn formats_lengths_in_meters_or_kilometers() {
+        assert_eq!(format_length(500.0), "500 m");
+        assert_eq!(format_length(2000.0), "2 km");
+        assert_eq!(format_length(1500.0), "1.5 km");
+    }
+}
@@ -0,0 +1,154 @@
+use crate::configuration::{AppConfig, OutputFormat};
+use crate::image_processing::{create_font_ref, load_font_data};
+use crate::print::save_for_print;
+use crate::text_processing::{text_mask, TextSpan};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageFormat, RgbImage};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor};
+use std::path::Path;
+
+fn create_file(path: &Path) -> Result<BufWriter<File>, Box<dyn std::error::Error>> {
+    let file =
+        File::create(path).map_err(|e| format!("Failed to create {} - {}", path.display(), e))?;
+    Ok(BufWriter::new(file))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// `@font-face` rule embedding a font file, so the SVG looks the same everywhere
+fn font_face(family: &str, weight: &str, path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let font_data = load_font_data(path)?;
+    let mime = if path.to_lowercase().ends_with(".otf") {
+        "font/otf"
+    } else {
+        "font/ttf"
+    };
+    Ok(format!(
+        "@font-face {{ font-family: \"{}\"; font-weight: {}; src: url(data:{};base64,{}); }}",
+        family,
+        weight,
+        mime,
+        BASE64.encode(font_data)
+    ))
+}
+
+// Write the layout as SVG: everything but the text as an embedded PNG, with the
+// text on top as selectable, scalable SVG text in the embedded layout fonts
+fn save_svg(
+    layout: &RgbImage,
+    text: &[TextSpan],
+    path: &Path,
+    config: &AppConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png_data = Vec::new();
+    layout.write_to(&mut Cursor::new(&mut png_data), ImageFormat::Png)?;
+    let (width, height) = layout.dimensions();
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" xml:space=\"preserve\">",
+        width, height
+    )?;
+    writeln!(svg, "<style>")?;
+    writeln!(
+        svg,
+        "{}",
+        font_face("layout-regular", "normal", &config.font.path_regular)?
+    )?;
+    writeln!(
+        svg,
+        "{}",
+        font_face("layout-bold", "bold", &config.font.path_bold)?
+    )?;
+    writeln!(svg, "text {{ white-space: pre; fill: #000000; }}")?;
+    writeln!(
+        svg,
+        ".regular {{ font-family: \"layout-regular\", sans-serif; }}"
+    )?;
+    // Viewers that ignore the embedded fonts still pick a bold face
+    writeln!(
+        svg,
+        ".bold {{ font-family: \"layout-bold\", sans-serif; font-weight: bold; }}"
+    )?;
+    writeln!(svg, "</style>")?;
+    writeln!(
+        svg,
+        "<image width=\"{}\" height=\"{}\" href=\"data:image/png;base64,{}\"/>",
+        width,
+        height,
+        BASE64.encode(&png_data)
+    )?;
+    for span in text {
+        writeln!(
+            svg,
+            "<text class=\"{}\" x=\"{:.1}\" y=\"{:.1}\" font-size=\"{:.2}\">{}</text>",
+            if span.bold { "bold" } else { "regular" },
+            span.x,
+            span.y,
+            span.font_size,
+            escape_xml(&span.text)
+        )?;
+    }
+    writeln!(svg, "</svg>")?;
+
+    fs::write(path, svg).map_err(|e| format!("Failed to write {} - {}", path.display(), e))?;
+    Ok(())
+}
+
+// Whether layouts are drawn without their text, which is recorded and added when
+// saving: as vector text in SVG, and as solid black when printing
+pub fn records_text(config: &AppConfig) -> bool {
+    match config.output_format {
+        OutputFormat::Svg => true,
+        OutputFormat::Png => config.print.is_some(),
+        _ => false,
+    }
+}
+
+// Save a finished layout in the configured output format. `text` holds the text
+// recorded when `records_text` is set and is empty otherwise
+pub fn save_layout(
+    layout: &RgbImage,
+    text: &[TextSpan],
+    path: &Path,
+    config: &AppConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match config.output_format {
+        OutputFormat::Png => match &config.print {
+            Some(print) => {
+                let font_data = load_font_data(&config.font.path_regular)?;
+                let font_regular = create_font_ref(&font_data)?;
+                let font_data = load_font_data(&config.font.path_bold)?;
+                let font_bold = create_font_ref(&font_data)?;
+                let (width, height) = layout.dimensions();
+                let mask = text_mask(text, width, height, &font_regular, &font_bold);
+                save_for_print(layout, &mask, path, print)?
+            }
+            None => layout.save_with_format(path, ImageFormat::Png)?,
+        },
+        OutputFormat::Jpeg => {
+            let quality = config.jpeg_quality.clamp(1, 100);
+            JpegEncoder::new_with_quality(create_file(path)?, quality).encode_image(layout)?;
+        }
+        OutputFormat::WebP => WebPEncoder::new_lossless(create_file(path)?).encode(
+            layout.as_raw(),
+            layout.width(),
+            layout.height(),
+            ExtendedColorType::Rgb8,
+        )?,
+        OutputFormat::Tiff => layout.save_with_format(path, ImageFormat::Tiff)?,
+        OutputFormat::Svg => save_svg(layout, text, path, config)?,
+    }
+    Ok(())
+}
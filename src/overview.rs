@@ -0,0 +1,202 @@
+use crate::configuration::{Alignment, AppConfig, MapSidecar};
+use crate::image_processing::{
+    blend_color, create_font_ref, load_font_data, parse_hex_color, text_variables,
+};
+use crate::process_images::collect_maps;
+use crate::text_processing::process_text;
+use ab_glyph::PxScale;
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_circle_mut, draw_polygon_mut, text_size};
+use imageproc::point::Point;
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Color of territories whose zone is not configured
+const DEFAULT_ZONE_COLOR: Rgb<u8> = Rgb([128u8, 128u8, 128u8]);
+const DEFAULT_ZONE_OPACITY: f32 = 0.3;
+
+// Center of mass of a polygon, or the mean of its points when it has no area
+fn polygon_centroid(points: &[[f32; 2]]) -> (f32, f32) {
+    let mut area = 0.0;
+    let (mut center_x, mut center_y) = (0.0, 0.0);
+    for (index, [x0, y0]) in points.iter().enumerate() {
+        let [x1, y1] = points[(index + 1) % points.len()];
+        let cross = x0 * y1 - x1 * y0;
+        area += cross;
+        center_x += (x0 + x1) * cross;
+        center_y += (y0 + y1) * cross;
+    }
+    if area.abs() < f32::EPSILON {
+        let count = points.len() as f32;
+        return (
+            points.iter().map(|[x, _]| x).sum::<f32>() / count,
+            points.iter().map(|[_, y]| y).sum::<f32>() / count,
+        );
+    }
+    (center_x / (3.0 * area), center_y / (3.0 * area))
+}
+
+fn to_points(boundary: &[[f32; 2]]) -> Vec<Point<i32>> {
+    let mut points: Vec<_> = boundary
+        .iter()
+        .map(|[x, y]| Point::new(x.round() as i32, y.round() as i32))
+        .collect();
+    // imageproc rejects polygons whose last point repeats the first
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+// Tint the inside of the polygon with the zone color
+fn fill_polygon(overview: &mut RgbImage, boundary: &[[f32; 2]], color: Rgb<u8>, opacity: f32) {
+    let points = to_points(boundary);
+    if points.len() < 3 {
+        return;
+    }
+    let mut mask = GrayImage::new(overview.width(), overview.height());
+    draw_polygon_mut(&mut mask, &points, Luma([255u8]));
+    for (x, y, inside) in mask.enumerate_pixels() {
+        if inside[0] > 0 {
+            let pixel = overview.get_pixel_mut(x, y);
+            *pixel = blend_color(*pixel, color, opacity);
+        }
+    }
+}
+
+// Draw the polygon outline as `width` pixel wide segments with round joints
+fn draw_outline(overview: &mut RgbImage, boundary: &[[f32; 2]], color: Rgb<u8>, width: u32) {
+    let half = width.max(1) as f32 / 2.0;
+    for (index, [x0, y0]) in boundary.iter().enumerate() {
+        let [x1, y1] = boundary[(index + 1) % boundary.len()];
+        let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+        if length > 0.0 {
+            let (normal_x, normal_y) = (-(y1 - y0) / length * half, (x1 - x0) / length * half);
+            let corners = [
+                (x0 + normal_x, y0 + normal_y),
+                (x1 + normal_x, y1 + normal_y),
+                (x1 - normal_x, y1 - normal_y),
+                (x0 - normal_x, y0 - normal_y),
+            ];
+            let corners: Vec<_> = corners
+                .iter()
+                .map(|(x, y)| Point::new(x.round() as i32, y.round() as i32))
+                .collect();
+            if corners.first() != corners.last() {
+                draw_polygon_mut(overview, &corners, color);
+            }
+        }
+        draw_filled_circle_mut(
+            overview,
+            (x0.round() as i32, y0.round() as i32),
+            half.round() as i32,
+            color,
+        );
+    }
+}
+
+// Compose the congregation overview: the base map with every territory boundary
+// tinted by zone color and labeled with its number
+pub fn create_overview(config: &AppConfig) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let overview_config = config
+        .overview
+        .as_ref()
+        .ok_or("The overview map is not configured")?;
+
+    let mut overview = image::open(&overview_config.base_map)
+        .map_err(|e| {
+            format!(
+                "Failed to open base map {} - {}",
+                overview_config.base_map, e
+            )
+        })?
+        .to_rgb8();
+
+    let font_data = load_font_data(&config.font.path_regular)?;
+    let font_regular = create_font_ref(&font_data)?;
+    let font_data = load_font_data(&config.font.path_bold)?;
+    let font_bold = create_font_ref(&font_data)?;
+    let scale = PxScale::from(overview_config.font_size);
+
+    let maps = collect_maps(Path::new(&config.map.maps_directory))?;
+    let mut territories = Vec::new();
+    for map in &maps {
+        // A broken sidecar only leaves its territory out of the overview
+        let sidecar = match MapSidecar::load(&map.path) {
+            Ok(sidecar) => sidecar,
+            Err(e) => {
+                warn!("Skipping {} in the overview - {}", map.name.display(), e);
+                continue;
+            }
+        };
+        let (number, zone_name) = match map.territory(config.map.zone_from_folder, &sidecar) {
+            Some(territory) => territory,
+            None => continue,
+        };
+        let boundary = match sidecar.boundary {
+            Some(boundary) if boundary.len() >= 3 => boundary,
+            _ => continue,
+        };
+        let (color, opacity) = match config.find_zone(&zone_name) {
+            Some(zone) => (parse_hex_color(&zone.color)?, zone.opacity),
+            None => (DEFAULT_ZONE_COLOR, DEFAULT_ZONE_OPACITY),
+        };
+        territories.push((number, zone_name, boundary, color, opacity));
+    }
+
+    // Fill every territory before drawing outlines and labels so none is covered
+    for (_, _, boundary, color, opacity) in &territories {
+        fill_polygon(&mut overview, boundary, *color, *opacity);
+    }
+    for (_, _, boundary, color, _) in &territories {
+        draw_outline(&mut overview, boundary, *color, config.map.boundary_width);
+    }
+    for (number, zone_name, boundary, _, _) in &territories {
+        let (center_x, center_y) = polygon_centroid(boundary);
+        let label = format!("**{}**", number);
+        let (label_w, label_h) = text_size(scale, &font_bold, number);
+        process_text(
+            &label,
+            &text_variables(zone_name, number),
+            &font_regular,
+            &font_bold,
+            false,
+            scale,
+            &mut overview,
+            None,
+            (center_x - label_w as f32 / 2.0).max(0.0) as u32,
+            (center_y - label_h as f32 / 2.0).max(0.0) as u32,
+            Alignment::Left,
+        )?;
+    }
+
+    let output_directory = Path::new(&config.output_directory);
+    fs::create_dir_all(output_directory)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    let output_path = output_directory.join(&overview_config.output_filename);
+    overview.save(&output_path)?;
+    Ok(output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centroid_of_a_polygon() {
+        let square = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+        assert_eq!(polygon_centroid(&square), (5.0, 5.0));
+        // Clockwise points give the same center
+        let reversed: Vec<_> = square.iter().rev().copied().collect();
+        assert_eq!(polygon_centroid(&reversed), (5.0, 5.0));
+        let triangle = [[0.0, 0.0], [6.0, 0.0], [0.0, 3.0]];
+        assert_eq!(polygon_centroid(&triangle), (2.0, 1.0));
+    }
+
+    #[test]
+    fn centroid_of_points_without_area() {
+        let line = [[0.0, 0.0], [4.0, 2.0], [8.0, 4.0]];
+        assert_eq!(polygon_centroid(&line), (4.0, 2.0));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::configuration::{Alignment, AppConfig, PagesConfig};
+use crate::image_processing::{
+    create_font_ref, draw_outline_rect, load_font_data, overlay_image, pixel_scale, text_variables,
+    MapImage,
+};
+use crate::map_overlays::corner_position;
+use crate::text_processing::{process_text, TextSpan};
+use ab_glyph::PxScale;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_rect_mut, text_size};
+use imageproc::rect::Rect;
+
+// Number of pieces of `piece` pixels, overlapping by `overlap`, needed to cover `length`
+fn piece_count(length: u32, piece: u32, overlap: f32) -> u32 {
+    if piece >= length {
+        return 1;
+    }
+    let step = piece as f32 * (1.0 - overlap.clamp(0.0, 0.9));
+    ((length as f32 - piece as f32) / step).ceil() as u32 + 1
+}
+
+// Start of each piece, spread evenly so the first and last touch the edges
+fn piece_starts(length: u32, piece: u32, count: u32) -> Vec<u32> {
+    if count <= 1 {
+        return vec![0];
+    }
+    (0..count)
+        .map(|index| ((length - piece) as f32 * index as f32 / (count - 1) as f32).round() as u32)
+        .collect()
+}
+
+// Split the map into the pieces shown on each page, left to right and top to bottom.
+// A map that fits the map area at `min_scale` or more stays on a single page
+pub fn plan_pages(map: &MapImage, area: Rect, pages: &PagesConfig) -> Vec<Rect> {
+    let (map_w, map_h) = map.image.dimensions();
+    let fit_scale = f32::min(
+        area.width() as f32 / map_w as f32,
+        area.height() as f32 / map_h as f32,
+    );
+    if pages.min_scale <= 0.0 || fit_scale >= pages.min_scale {
+        return vec![Rect::at(0, 0).of_size(map_w, map_h)];
+    }
+
+    let piece_w = ((area.width() as f32 / pages.min_scale) as u32).clamp(1, map_w);
+    let piece_h = ((area.height() as f32 / pages.min_scale) as u32).clamp(1, map_h);
+    let columns = piece_starts(map_w, piece_w, piece_count(map_w, piece_w, pages.overlap));
+    let rows = piece_starts(map_h, piece_h, piece_count(map_h, piece_h, pages.overlap));
+
+    rows.iter()
+        .flat_map(|y| {
+            columns
+                .iter()
+                .map(move |x| Rect::at(*x as i32, *y as i32).of_size(piece_w, piece_h))
+        })
+        .collect()
+}
+
+// Draw a small copy of the whole map with the part shown on this page highlighted
+pub fn draw_locator(
+    layout: &mut RgbImage,
+    map_rect: Rect,
+    map: &MapImage,
+    piece: Rect,
+    pages: &PagesConfig,
+    config: &AppConfig,
+) {
+    let (map_w, map_h) = map.image.dimensions();
+    let scale = f32::min(
+        pages.locator_size as f32 / map_w as f32,
+        pages.locator_size as f32 / map_h as f32,
+    );
+    let thumbnail_w = ((map_w as f32 * scale).round() as u32).max(1);
+    let thumbnail_h = ((map_h as f32 * scale).round() as u32).max(1);
+    let thumbnail = image::imageops::thumbnail(&map.image, thumbnail_w, thumbnail_h);
+
+    let (x, y) = corner_position(
+        map_rect,
+        pages.locator_corner,
+        thumbnail_w,
+        thumbnail_h,
+        config,
+    );
+    let inset = Rect::at(x, y).of_size(thumbnail_w, thumbnail_h);
+    draw_filled_rect_mut(layout, inset, Rgb([255u8, 255u8, 255u8]));
+    overlay_image(layout, &thumbnail, x.into(), y.into());
+    draw_outline_rect(layout, inset, Rgb([0u8, 0u8, 0u8]), pixel_scale(config));
+
+    let highlight_x = x + (piece.left() as f32 * scale).round() as i32;
+    let highlight_y = y + (piece.top() as f32 * scale).round() as i32;
+    let highlight_w = ((piece.width() as f32 * scale).round() as u32).max(3);
+    let highlight_h = ((piece.height() as f32 * scale).round() as u32).max(3);
+    draw_outline_rect(
+        layout,
+        Rect::at(highlight_x, highlight_y).of_size(highlight_w, highlight_h),
+        Rgb([220u8, 0u8, 0u8]),
+        2 * pixel_scale(config),
+    );
+}
+
+// Draw the page number, e.g. `2/4`, centered in the bottom margin
+#[allow(clippy::too_many_arguments)]
+pub fn draw_page_label(
+    layout: &mut RgbImage,
+    config: &AppConfig,
+    pages: &PagesConfig,
+    name: &str,
+    number: &str,
+    page: usize,
+    total: usize,
+    spans: Option<&mut Vec<TextSpan>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let font_data = load_font_data(&config.font.path_regular)?;
+    let font_regular = create_font_ref(&font_data)?;
+    let font_data = load_font_data(&config.font.path_bold)?;
+    let font_bold = create_font_ref(&font_data)?;
+    let scale = PxScale::from(config.font.size_subtitle);
+
+    let mut variables = text_variables(name, number);
+    variables.push(("page".to_string(), page.to_string()));
+    variables.push(("total".to_string(), total.to_string()));
+
+    let (label_w, _) = text_size(scale, &font_regular, &pages.label);
+    let margin = config.layout.margin;
+    let label_x = layout.width().saturating_sub(label_w) / 2;
+    let label_y = layout.height().saturating_sub(margin)
+        + margin.saturating_sub(config.font.size_subtitle.ceil() as u32) / 2;
+
+    process_text(
+        &pages.label,
+        &variables,
+        &font_regular,
+        &font_bold,
+        false,
+        scale,
+        layout,
+        spans,
+        label_x,
+        label_y,
+        Alignment::Center,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::Corner;
+    use image::RgbaImage;
+
+    #[test]
+    fn counts_the_pieces_covering_a_length() {
+        assert_eq!(piece_count(1000, 1000, 0.1), 1);
+        assert_eq!(piece_count(1000, 1200, 0.1), 1);
+        assert_eq!(piece_count(1000, 500, 0.0), 2);
+        assert_eq!(piece_count(1000, 400, 0.0), 3);
+        assert_eq!(piece_count(1000, 500, 0.1), 3);
+    }
+
+    #[test]
+    fn spreads_pieces_from_edge_to_edge() {
+        assert_eq!(piece_starts(1000, 1000, 1), vec![0]);
+        assert_eq!(piece_starts(1000, 500, 2), vec![0, 500]);
+        assert_eq!(piece_starts(1000, 400, 3), vec![0, 300, 600]);
+    }
+
+    #[test]
+    fn splits_only_maps_below_the_minimum_scale() {
+        let pages = PagesConfig {
+            min_scale: 0.5,
+            overlap: 0.1,
+            label: String::new(),
+            locator_corner: Corner::TopLeft,
+            locator_size: 100,
+        };
+        let area = Rect::at(0, 0).of_size(100, 100);
+        let map = |w, h| MapImage {
+            image: RgbaImage::new(w, h),
+            rotation: 0.0,
+        };
+
+        let single = plan_pages(&map(180, 120), area, &pages);
+        assert_eq!(single, vec![Rect::at(0, 0).of_size(180, 120)]);
+
+        let split = plan_pages(&map(400, 200), area, &pages);
+        let starts: Vec<_> = split.iter().map(|rect| (rect.left(), rect.top())).collect();
+        assert_eq!(starts, vec![(0, 0), (100, 0), (200, 0)]);
+        assert!(split
+            .iter()
+            .all(|rect| (rect.width(), rect.height()) == (200, 200)));
+    }
+}
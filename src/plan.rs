@@ -0,0 +1,163 @@
+use crate::build_cache::{territory_fingerprint, BuildCache};
+use crate::configuration::{AppConfig, MapSidecar};
+use crate::image_processing::{load_map, map_scale};
+use crate::process_images::{collect_maps, map_pieces, output_path, MapSource};
+use crossterm::style::{Color, Stylize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Files in the maps directory tree that are neither a map tile nor a map's sidecar
+fn unused_files(
+    directory: &Path,
+    used: &BTreeSet<PathBuf>,
+    unused: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(directory)
+        .map_err(|e| format!("Failed to read directory {}: {}", directory.display(), e))?
+    {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            unused_files(&path, used, unused)?;
+        } else if !used.contains(&path) {
+            unused.push(path);
+        }
+    }
+    Ok(())
+}
+
+// What processing a map would do, or why it would be rejected
+fn plan_map(
+    config: &AppConfig,
+    map: &MapSource,
+    force: bool,
+    cache: &BuildCache,
+) -> Result<(Vec<String>, bool), String> {
+    let sidecar = MapSidecar::load(&map.path).map_err(|e| e.to_string())?;
+    let (territory_number, zone_name) = map
+        .territory(config.map.zone_from_folder, &sidecar)
+        .ok_or("invalid filename, expected <number>-<zone>, e.g. 12-casal-monastero")?;
+    map.check_tiles()?;
+    let map_image = load_map(&map.tiles, config, &sidecar).map_err(|e| e.to_string())?;
+
+    // Pages and scale as `render_territory` works them out, on the supersampled layout
+    let supersample = config.layout.supersample.clamp(1, 4);
+    let render_config = config.scaled(supersample);
+    let pieces = map_pieces(&map_image, &render_config);
+    let (scale_x, scale_y) = map_scale(&render_config, pieces[0].width(), pieces[0].height());
+    let (scale_x, scale_y) = (scale_x / supersample as f32, scale_y / supersample as f32);
+
+    let crop = sidecar.crop.unwrap_or(config.map.crop);
+    let mut lines = vec![
+        format!("territory {}, zone {}", territory_number, zone_name),
+        format!(
+            "crop top {}, left {}, bottom {}, right {}{} -> {}x{} px, rotation {}°",
+            crop.top,
+            crop.left,
+            crop.bottom,
+            crop.right,
+            if sidecar.crop.is_some() {
+                " (sidecar)"
+            } else {
+                ""
+            },
+            map_image.image.width(),
+            map_image.image.height(),
+            map_image.rotation
+        ),
+    ];
+    let scale = if scale_x == scale_y {
+        format!("{:.3}", scale_x)
+    } else {
+        format!("{:.3} x {:.3}", scale_x, scale_y)
+    };
+    lines.push(format!(
+        "scale {} ({:?}), {} page(s)",
+        scale,
+        config.map.placement,
+        pieces.len()
+    ));
+
+    let output_directory = Path::new(&config.output_directory).join(&map.folder);
+    for page in 1..=pieces.len() {
+        let path = output_path(
+            config,
+            &output_directory,
+            &territory_number,
+            &zone_name,
+            page,
+            pieces.len(),
+        );
+        lines.push(format!("-> {}", path.display()));
+    }
+
+    let fingerprint = territory_fingerprint(config, map).map_err(|e| e.to_string())?;
+    let up_to_date = !force && cache.up_to_date(&map.name, &fingerprint).is_some();
+    Ok((lines, up_to_date))
+}
+
+// Print what `process_images` would do with each map without writing anything:
+// the parsed territory, effective crop and scale, output files, and the rejected files
+pub fn print_plan(config: &AppConfig, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let maps_directory = Path::new(&config.map.maps_directory);
+    let maps = collect_maps(maps_directory)?;
+    let cache = BuildCache::load(Path::new(&config.output_directory));
+
+    let mut render_count = 0;
+    let mut skipped_count = 0;
+    let mut rejected = Vec::new();
+    for map in &maps {
+        match plan_map(config, map, force, &cache) {
+            Ok((lines, up_to_date)) => {
+                let status = if up_to_date {
+                    skipped_count += 1;
+                    "up to date, skipped".with(Color::DarkGrey)
+                } else {
+                    render_count += 1;
+                    "render".with(Color::Green)
+                };
+                println!("\r{} ({})", map.name.display().to_string().bold(), status);
+                for line in lines {
+                    println!("\r\t{}", line);
+                }
+            }
+            Err(reason) => rejected.push((map.name.display().to_string(), reason)),
+        }
+    }
+
+    // Anything else in the maps directory is left out of the batch
+    let mut used = BTreeSet::new();
+    for map in &maps {
+        used.extend(map.tiles.iter().cloned());
+        used.insert(MapSidecar::path_for(&map.path));
+    }
+    let mut unused = Vec::new();
+    unused_files(maps_directory, &used, &mut unused)?;
+    for path in unused {
+        let name = path.strip_prefix(maps_directory).unwrap_or(&path);
+        let reason = if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("toml"))
+        {
+            "sidecar without a map"
+        } else {
+            "not an image format that can be read"
+        };
+        rejected.push((name.display().to_string(), reason.to_string()));
+    }
+
+    if !rejected.is_empty() {
+        println!("\n\r{}", "Rejected:".with(Color::Red));
+        for (name, reason) in &rejected {
+            println!("\r\t{}: {}", name, reason);
+        }
+    }
+    println!(
+        "\n\r{} to render, {} up to date, {} rejected. Nothing was written.",
+        render_count,
+        skipped_count,
+        rejected.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use crate::configuration::{Dither, PrintConfig};
+use crate::image_processing::parse_hex_color;
+use image::{GrayImage, Rgb, RgbImage};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+const BLACK: Rgb<u8> = Rgb([0u8, 0u8, 0u8]);
+
+// Thresholds of the 4x4 ordered dithering pattern
+const BAYER_MATRIX: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+// The configured colors, or evenly spaced shades of gray, always including black
+fn build_palette(print: &PrintConfig) -> Result<Vec<Rgb<u8>>, Box<dyn std::error::Error>> {
+    let mut palette = if print.colors.is_empty() {
+        let levels = print.gray_levels.clamp(2, 256);
+        (0..levels)
+            .map(|level| {
+                let gray = (level as f32 * 255.0 / (levels - 1) as f32).round() as u8;
+                Rgb([gray, gray, gray])
+            })
+            .collect()
+    } else {
+        print
+            .colors
+            .iter()
+            .map(|color| parse_hex_color(color))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    if !palette.contains(&BLACK) {
+        palette.insert(0, BLACK);
+    }
+    if palette.len() > 256 {
+        return Err(format!(
+            "The print palette has {} colors, indexed PNG allows at most 256",
+            palette.len()
+        )
+        .into());
+    }
+    Ok(palette)
+}
+
+fn nearest_color(palette: &[Rgb<u8>], [r, g, b]: [f32; 3]) -> usize {
+    let distance = |color: &Rgb<u8>| {
+        (color[0] as f32 - r).powi(2)
+            + (color[1] as f32 - g).powi(2)
+            + (color[2] as f32 - b).powi(2)
+    };
+    (0..palette.len())
+        .min_by(|a, b| distance(&palette[*a]).total_cmp(&distance(&palette[*b])))
+        .unwrap_or(0)
+}
+
+// Palette index of every pixel of the layout, dithered as configured. Pixels mostly
+// covered by text in `text_mask` are solid black, so the text isn't dotted
+fn reduce_colors(
+    layout: &RgbImage,
+    text_mask: &GrayImage,
+    palette: &[Rgb<u8>],
+    print: &PrintConfig,
+) -> Vec<u8> {
+    let (width, height) = (layout.width() as usize, layout.height() as usize);
+    let black = palette
+        .iter()
+        .position(|color| *color == BLACK)
+        .unwrap_or(0);
+    // Typical distance between palette colors, used to size the ordered pattern
+    let spread = 255.0 / (palette.len() - 1).max(1) as f32;
+    let mut errors = vec![[0.0f32; 3]; width * height];
+    let mut indices = vec![0u8; width * height];
+
+    for (x, y, pixel) in layout.enumerate_pixels() {
+        let (x, y) = (x as usize, y as usize);
+        let i = y * width + x;
+        let [r, g, b] = pixel.0.map(|c| c as f32);
+        if text_mask.get_pixel(x as u32, y as u32)[0] >= 128 {
+            indices[i] = black as u8;
+            continue;
+        }
+
+        let index = match print.dither {
+            Dither::None => nearest_color(palette, [r, g, b]),
+            Dither::Ordered => {
+                let offset = ((BAYER_MATRIX[y % 4][x % 4] + 0.5) / 16.0 - 0.5) * spread;
+                nearest_color(palette, [r + offset, g + offset, b + offset])
+            }
+            Dither::FloydSteinberg => {
+                let value = [r + errors[i][0], g + errors[i][1], b + errors[i][2]];
+                let index = nearest_color(palette, value);
+                let color = palette[index];
+                let error = [
+                    value[0] - color[0] as f32,
+                    value[1] - color[1] as f32,
+                    value[2] - color[2] as f32,
+                ];
+                let mut spread_error = |dx: isize, dy: usize, weight: f32| {
+                    let (nx, ny) = (x as isize + dx, y + dy);
+                    if nx >= 0 && (nx as usize) < width && ny < height {
+                        let target = &mut errors[ny * width + nx as usize];
+                        for channel in 0..3 {
+                            target[channel] += error[channel] * weight;
+                        }
+                    }
+                };
+                spread_error(1, 0, 7.0 / 16.0);
+                spread_error(-1, 1, 3.0 / 16.0);
+                spread_error(0, 1, 5.0 / 16.0);
+                spread_error(1, 1, 1.0 / 16.0);
+                index
+            }
+        };
+        indices[i] = index as u8;
+    }
+    indices
+}
+
+// Write the palette indices as a PNG with the smallest bit depth that fits
+fn write_indexed_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    palette: &[Rgb<u8>],
+    indices: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (bits, depth) = match palette.len() {
+        0..=2 => (1, png::BitDepth::One),
+        3..=4 => (2, png::BitDepth::Two),
+        5..=16 => (4, png::BitDepth::Four),
+        _ => (8, png::BitDepth::Eight),
+    };
+    let row_bytes = (width as usize * bits).div_ceil(8);
+    let mut data = vec![0u8; row_bytes * height as usize];
+    for (i, index) in indices.iter().enumerate() {
+        let (x, y) = (i % width as usize, i / width as usize);
+        let bit = x * bits;
+        data[y * row_bytes + bit / 8] |= index << (8 - bits - bit % 8);
+    }
+
+    let file =
+        File::create(path).map_err(|e| format!("Failed to create {} - {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|color| color.0)
+            .collect::<Vec<u8>>(),
+    );
+    encoder.set_compression(png::Compression::Best);
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+// Save a finished layout reduced to the printer palette. The layout comes without its
+// text, which is recorded in `text` and printed on top as solid black
+pub fn save_for_print(
+    layout: &RgbImage,
+    text_mask: &GrayImage,
+    path: &Path,
+    print: &PrintConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let palette = build_palette(print)?;
+    let indices = reduce_colors(layout, text_mask, &palette, print);
+    write_indexed_png(path, layout.width(), layout.height(), &palette, &indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print_config(colors: &[&str], gray_levels: u32) -> PrintConfig {
+        PrintConfig {
+            colors: colors.iter().map(|color| color.to_string()).collect(),
+            gray_levels,
+            dither: Dither::None,
+        }
+    }
+
+    #[test]
+    fn builds_gray_or_configured_palettes_with_black() {
+        let grays = build_palette(&print_config(&[], 3)).unwrap();
+        assert_eq!(
+            grays,
+            vec![Rgb([0, 0, 0]), Rgb([128, 128, 128]), Rgb([255, 255, 255])]
+        );
+        let colors = build_palette(&print_config(&["#ffffff", "#ed5858"], 0)).unwrap();
+        assert_eq!(
+            colors,
+            vec![BLACK, Rgb([255, 255, 255]), Rgb([237, 88, 88])]
+        );
+        assert!(build_palette(&print_config(&["red"], 0)).is_err());
+    }
+
+    #[test]
+    fn picks_the_nearest_palette_color() {
+        let palette = [BLACK, Rgb([255, 255, 255]), Rgb([237, 88, 88])];
+        assert_eq!(nearest_color(&palette, [20.0, 10.0, 0.0]), 0);
+        assert_eq!(nearest_color(&palette, [250.0, 240.0, 245.0]), 1);
+        assert_eq!(nearest_color(&palette, [200.0, 60.0, 70.0]), 2);
+    }
+
+    #[test]
+    fn text_is_black_and_dark_map_keeps_its_color() {
+        let palette = [BLACK, Rgb([60, 60, 60]), Rgb([255, 255, 255])];
+        // A dark map pixel, a text pixel over the white paper and an antialiased edge
+        let layout = RgbImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgb([50, 50, 50]),
+            _ => Rgb([250, 250, 250]),
+        });
+        let mut mask = GrayImage::new(3, 1);
+        mask.put_pixel(1, 0, image::Luma([255]));
+        mask.put_pixel(2, 0, image::Luma([40]));
+        assert_eq!(
+            reduce_colors(&layout, &mask, &palette, &print_config(&[], 0)),
+            vec![1, 0, 2]
+        );
+    }
+
+    #[test]
+    fn text_stays_solid_when_dithered() {
+        let palette = build_palette(&print_config(&[], 2)).unwrap();
+        let layout = RgbImage::from_pixel(8, 8, Rgb([128, 128, 128]));
+        let mask = GrayImage::from_pixel(8, 8, image::Luma([255]));
+        let print = PrintConfig {
+            dither: Dither::FloydSteinberg,
+            ..print_config(&[], 2)
+        };
+        assert!(reduce_colors(&layout, &mask, &palette, &print)
+            .iter()
+            .all(|index| *index == 0));
+    }
+}
@@ -1,22 +1,630 @@
-use crate::configuration::AppConfig;
-use crate::image_processing::{add_map_image, create_layout};
-use crate::text_processing::title_case;
-use crossterm::style::{Color, Stylize};
+use crate::build_cache::{territory_fingerprint, BuildCache, CacheEntry};
+use crate::configuration::{AppConfig, MapSidecar};
+use crate::contact_sheet::{create_contact_sheet, sheet_thumbnail, SheetEntry};
+use crate::image_processing::{
+    add_layout_images, add_map_image, create_font_ref, create_layout, load_font_data, load_map,
+    map_area, text_variables, MapImage,
+};
+use crate::legend::add_legend;
+use crate::logging::{open_log_file, set_progress_bar};
+use crate::output::{records_text, save_layout};
+use crate::pages::{draw_locator, draw_page_label, plan_pages};
+use crate::qr_code::add_qr_code;
+use crate::report::{write_report, ReportEntry};
+use crate::text_processing::{draw_spans, title_case, TextSpan};
+use image::imageops::FilterType;
+use image::{ImageFormat, RgbImage};
+use imageproc::rect::Rect;
 use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-pub fn process_images(config: &AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+// A territory map found in the maps directory, made of one or more screenshot tiles
+#[derive(Clone)]
+pub struct MapSource {
+    // Map path without the tile suffix, e.g. `maps/12-zone.jpg`, used to find the sidecar
+    pub path: PathBuf,
+    pub tiles: Vec<PathBuf>,
+    // Subfolder of the maps directory holding the map, mirrored in the output directory
+    pub folder: PathBuf,
+    // Map path inside the maps directory without extension, e.g. `casal-monastero/12`,
+    // used in messages and as build cache key
+    pub name: PathBuf,
+}
+
+impl MapSource {
+    // Territory number and zone name, as set in the sidecar or else parsed from the path
+    pub fn territory(
+        &self,
+        zone_from_folder: bool,
+        sidecar: &MapSidecar,
+    ) -> Option<(String, String)> {
+        let parsed = self.parsed_territory(zone_from_folder);
+        let number = match &sidecar.territory_number {
+            Some(number) => number.clone(),
+            None => parsed.as_ref()?.0.clone(),
+        };
+        let zone = match &sidecar.zone_name {
+            Some(zone) => zone.clone(),
+            None => parsed?.1,
+        };
+        Some((number, zone))
+    }
+
+    // Territory number and zone name from a filename like `12-casal-monastero`, or with
+    // `zone_from_folder` from a path like `casal-monastero/12` inside the maps directory
+    pub fn parsed_territory(&self, zone_from_folder: bool) -> Option<(String, String)> {
+        let filename = self.path.file_stem().and_then(|f| f.to_str())?;
+        let folder_name = self.folder.file_name().and_then(|f| f.to_str());
+        let (number, zone) = match folder_name {
+            Some(folder_name) if zone_from_folder => {
+                let number = filename.split('-').next().unwrap_or(filename);
+                (number, folder_name)
+            }
+            _ => filename.split_once('-')?,
+        };
+        Some((number.to_string(), title_case(&zone.replace("-", " "))))
+    }
+
+    // Fail on two files for the same tile, e.g. `12-zone.png` and `12-zone.jpg`, which
+    // would otherwise be stitched together
+    pub fn check_tiles(&self) -> Result<(), String> {
+        let tile_index = |path: &PathBuf| {
+            path.file_stem()
+                .and_then(|f| f.to_str())
+                .map(|stem| split_tile_suffix(stem).1)
+        };
+        for pair in self.tiles.windows(2) {
+            if tile_index(&pair[0]) == tile_index(&pair[1]) {
+                return Err(format!(
+                    "{} and {} are the same map tile, remove or rename one of them",
+                    pair[0].display(),
+                    pair[1].display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Split a tile suffix from a file stem, e.g. `12-zone.2` into `12-zone` and 2
+fn split_tile_suffix(stem: &str) -> (&str, u32) {
+    if let Some((base, suffix)) = stem.rsplit_once('.') {
+        if let Ok(tile) = suffix.parse::<u32>() {
+            return (base, tile);
+        }
+    }
+    (stem, 0)
+}
+
+// Add the images in `directory` and its subfolders to `images`. Any format the `image`
+// crate can read is accepted, whatever the case of the extension
+fn find_images(
+    directory: &Path,
+    images: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(directory)
+        .map_err(|e| format!("Failed to read directory {}: {}", directory.display(), e))?
+    {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_images(&path, images)?;
+            continue;
+        }
+        let is_image = path
+            .extension()
+            .and_then(ImageFormat::from_extension)
+            .is_some_and(|format| format.reading_enabled());
+        if is_image {
+            images.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Gather the maps in the directory tree, grouping the tiles of each territory in order
+pub fn collect_maps(maps_directory: &Path) -> Result<Vec<MapSource>, Box<dyn std::error::Error>> {
+    let mut images = Vec::new();
+    find_images(maps_directory, &mut images)?;
+
+    // Tiles are grouped by folder and base name, e.g. `zone/12.1.png` under `zone/12`
+    let mut groups: BTreeMap<PathBuf, Vec<(u32, PathBuf)>> = BTreeMap::new();
+    for path in images {
+        if let (Some(parent), Some(stem)) =
+            (path.parent(), path.file_stem().and_then(|f| f.to_str()))
+        {
+            let (base, tile) = split_tile_suffix(stem);
+            groups
+                .entry(parent.join(base))
+                .or_default()
+                .push((tile, path.clone()));
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(base, mut tiles)| {
+            tiles.sort();
+            let extension = tiles[0].1.extension().unwrap_or_default().to_string_lossy();
+            let path = PathBuf::from(format!("{}.{}", base.display(), extension));
+            let folder = path
+                .parent()
+                .and_then(|parent| parent.strip_prefix(maps_directory).ok())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            let name = base
+                .strip_prefix(maps_directory)
+                .map(Path::to_path_buf)
+                .unwrap_or(base);
+            MapSource {
+                path,
+                tiles: tiles.into_iter().map(|(_, path)| path).collect(),
+                folder,
+                name,
+            }
+        })
+        .collect())
+}
+
+// A layout file and its size in pixels
+pub struct SavedPage {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Files saved for a territory and their contact sheet entries
+pub struct RenderedTerritory {
+    pub outputs: Vec<SavedPage>,
+    pub sheet_entries: Vec<SheetEntry>,
+}
+
+fn sheet_entry(
+    thumbnail: RgbImage,
+    zone_name: &str,
+    territory_number: &str,
+    page: usize,
+    total: usize,
+) -> SheetEntry {
+    let mut variables = text_variables(zone_name, territory_number);
+    variables.push(("page".to_string(), page.to_string()));
+    variables.push(("total".to_string(), total.to_string()));
+    SheetEntry {
+        thumbnail,
+        variables,
+    }
+}
+
+// Contact sheet entries of a territory skipped as up to date, made from the thumbnails
+// saved with its pages. None when they are missing or have another width, so the
+// territory is rendered again
+fn cached_sheet_entries(
+    config: &AppConfig,
+    entry: &CacheEntry,
+    zone_name: &str,
+    territory_number: &str,
+) -> Option<Vec<SheetEntry>> {
+    let Some(sheet_config) = &config.contact_sheet else {
+        return Some(Vec::new());
+    };
+    if entry.thumbnails.len() != entry.outputs.len() {
+        return None;
+    }
+    entry
+        .thumbnails
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            let thumbnail = image::open(path).ok()?.to_rgb8();
+            (thumbnail.width() == sheet_config.thumbnail_width.max(1)).then(|| {
+                sheet_entry(
+                    thumbnail,
+                    zone_name,
+                    territory_number,
+                    index + 1,
+                    entry.thumbnails.len(),
+                )
+            })
+        })
+        .collect()
+}
+
+// Keep the contact sheet thumbnails of a rendered territory for the runs that skip it.
+// Returns the saved files, none if one failed since the entries are then incomplete
+fn save_thumbnails(config: &AppConfig, map: &MapSource, entries: &[SheetEntry]) -> Vec<PathBuf> {
+    let output_directory = Path::new(&config.output_directory);
+    let mut thumbnails = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let path = BuildCache::thumbnail_path(output_directory, &map.name, index + 1);
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| entry.thumbnail.save(&path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to save thumbnail {} - {}", path.display(), e);
+            return Vec::new();
+        }
+        thumbnails.push(path);
+    }
+    thumbnails
+}
+
+// Delete the files of earlier renders that the last one no longer produced
+pub fn remove_stale_files(files: &[PathBuf]) {
+    for file in files {
+        match fs::remove_file(file) {
+            Ok(()) => info!("Removed {}, no longer produced", file.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove {} - {}", file.display(), e),
+        }
+    }
+}
+
+// Parts of the map shown on each page, the whole map unless it has to be split
+pub fn map_pieces(map_image: &MapImage, render_config: &AppConfig) -> Vec<Rect> {
+    match &render_config.map.pages {
+        Some(pages) => plan_pages(map_image, map_area(render_config), pages),
+        None => vec![Rect::at(0, 0).of_size(map_image.image.width(), map_image.image.height())],
+    }
+}
+
+// Layout file of one page of a territory, e.g. `12-casal-monastero-p2.png`
+pub fn output_path(
+    config: &AppConfig,
+    output_directory: &Path,
+    territory_number: &str,
+    zone_name: &str,
+    page: usize,
+    total: usize,
+) -> PathBuf {
+    let output_name = format!(
+        "{}-{}",
+        territory_number,
+        zone_name.replace(" ", "-").to_lowercase()
+    );
+    let extension = config.output_format.extension();
+    let output_filename = if total > 1 {
+        format!("{}-p{}.{}", output_name, page, extension)
+    } else {
+        format!("{}.{}", output_name, extension)
+    };
+    output_directory.join(output_filename)
+}
+
+// Draw one page of a territory's layout, showing `pieces[index]` of the map. The
+// texts are added to `spans` instead of being drawn when they are given
+#[allow(clippy::too_many_arguments)]
+pub fn compose_page(
+    render_config: &AppConfig,
+    map_image: &MapImage,
+    pieces: &[Rect],
+    index: usize,
+    sidecar: &MapSidecar,
+    zone_name: &str,
+    territory_number: &str,
+    mut spans: Option<&mut Vec<TextSpan>>,
+) -> Result<RgbImage, Box<dyn std::error::Error>> {
+    let piece = pieces[index];
+    let mut layout = create_layout(
+        render_config,
+        zone_name,
+        territory_number,
+        spans.as_deref_mut(),
+    )?;
+    let map_rect = if pieces.len() == 1 {
+        add_map_image(
+            &mut layout,
+            map_image,
+            render_config,
+            sidecar,
+            spans.as_deref_mut(),
+        )?
+    } else {
+        add_map_image(
+            &mut layout,
+            &map_image.crop(piece),
+            render_config,
+            sidecar,
+            spans.as_deref_mut(),
+        )?
+    };
+    add_layout_images(&mut layout, render_config)?;
+    add_legend(
+        &mut layout,
+        render_config,
+        zone_name,
+        territory_number,
+        spans.as_deref_mut(),
+    )?;
+    add_qr_code(
+        &mut layout,
+        render_config,
+        zone_name,
+        territory_number,
+        sidecar,
+    )?;
+    if let Some(pages) = &render_config.map.pages {
+        if pieces.len() > 1 {
+            draw_locator(
+                &mut layout,
+                map_rect,
+                map_image,
+                piece,
+                pages,
+                render_config,
+            );
+            draw_page_label(
+                &mut layout,
+                render_config,
+                pages,
+                zone_name,
+                territory_number,
+                index + 1,
+                pieces.len(),
+                spans,
+            )?;
+        }
+    }
+    Ok(layout)
+}
+
+// First page of a territory's layout, drawn without supersampling for a quick preview
+pub fn preview_layout(
+    config: &AppConfig,
+    map: &MapSource,
+) -> Result<RgbImage, Box<dyn std::error::Error>> {
+    // Paddings and line widths follow `layout.supersample`, so draw at the final size
+    let mut config = config.clone();
+    config.layout.supersample = 1;
+    let sidecar = MapSidecar::load(&map.path)?;
+    let (territory_number, zone_name) = map
+        .territory(config.map.zone_from_folder, &sidecar)
+        .ok_or("Invalid filename format")?;
+    map.check_tiles()?;
+    let map_image = load_map(&map.tiles, &config, &sidecar)?;
+    let pieces = map_pieces(&map_image, &config);
+    compose_page(
+        &config,
+        &map_image,
+        &pieces,
+        0,
+        &sidecar,
+        &zone_name,
+        &territory_number,
+        None,
+    )
+}
+
+// Render the layout of one territory, split over several pages when the map is too large
+fn render_territory(
+    config: &AppConfig,
+    map: &MapSource,
+    sidecar: &MapSidecar,
+    territory_number: &str,
+    zone_name: &str,
+    output_directory: &Path,
+) -> Result<RenderedTerritory, Box<dyn std::error::Error>> {
+    // Supersampled layouts are drawn with every size scaled up, then shrunk when saved
+    let supersample = config.layout.supersample.clamp(1, 4);
+    let scaled_config;
+    let render_config = if supersample > 1 {
+        scaled_config = config.scaled(supersample);
+        &scaled_config
+    } else {
+        config
+    };
+    let load_time = Instant::now();
+    let map_image = load_map(&map.tiles, config, sidecar)?;
+    let pieces = map_pieces(&map_image, render_config);
+    debug!(
+        "{}: loaded {} tile(s) in {:.2?}, map {}x{} px, rotation {}°, {} page(s)",
+        map.name.display(),
+        map.tiles.len(),
+        load_time.elapsed(),
+        map_image.image.width(),
+        map_image.image.height(),
+        map_image.rotation,
+        pieces.len()
+    );
+
+    let mut rendered = RenderedTerritory {
+        outputs: Vec::new(),
+        sheet_entries: Vec::new(),
+    };
+    for index in 0..pieces.len() {
+        // SVG and print output keep the text out of the raster and add it when saving
+        let mut text = Vec::new();
+        let mut layout = compose_page(
+            render_config,
+            &map_image,
+            &pieces,
+            index,
+            sidecar,
+            zone_name,
+            territory_number,
+            records_text(config).then_some(&mut text),
+        )?;
+
+        if supersample > 1 {
+            // Triangle averages the neighbouring pixels, smoothing the edges of text and lines
+            layout = image::imageops::resize(
+                &layout,
+                config.layout.width,
+                config.layout.height,
+                FilterType::Triangle,
+            );
+            for span in &mut text {
+                span.x /= supersample as f32;
+                span.y /= supersample as f32;
+                span.font_size /= supersample as f32;
+            }
+        }
+
+        let output_path = output_path(
+            config,
+            output_directory,
+            territory_number,
+            zone_name,
+            index + 1,
+            pieces.len(),
+        );
+        let save_time = Instant::now();
+        save_layout(&layout, &text, &output_path, config)?;
+        debug!(
+            "{}: saved {} in {:.2?}",
+            map.name.display(),
+            output_path.display(),
+            save_time.elapsed()
+        );
+        rendered.outputs.push(SavedPage {
+            path: output_path,
+            width: layout.width(),
+            height: layout.height(),
+        });
+
+        if let Some(sheet_config) = &config.contact_sheet {
+            // The recorded text isn't in the raster, so draw it for the thumbnail
+            if !text.is_empty() {
+                let font_data = load_font_data(&config.font.path_regular)?;
+                let font_regular = create_font_ref(&font_data)?;
+                let font_data = load_font_data(&config.font.path_bold)?;
+                let font_bold = create_font_ref(&font_data)?;
+                draw_spans(&mut layout, &text, &font_regular, &font_bold);
+            }
+            rendered.sheet_entries.push(sheet_entry(
+                sheet_thumbnail(&layout, sheet_config),
+                zone_name,
+                territory_number,
+                index + 1,
+                pieces.len(),
+            ));
+        }
+    }
+    Ok(rendered)
+}
+
+// What processing one map came to
+pub enum MapOutcome {
+    Rendered(RenderedTerritory),
+    // The layouts saved by an earlier run were up to date
+    Skipped(RenderedTerritory),
+    Failed(String),
+    // The filename doesn't give a territory number and zone name
+    InvalidName,
+}
+
+// Render the layouts of one map unless the build cache finds them up to date,
+// recording the result in the cache
+pub fn process_map(
+    config: &AppConfig,
+    map: &MapSource,
+    force: bool,
+    cache: &mut BuildCache,
+) -> MapOutcome {
+    let sidecar = match MapSidecar::load(&map.path) {
+        Ok(sidecar) => sidecar,
+        Err(e) => return MapOutcome::Failed(e.to_string()),
+    };
+    let Some((territory_number, zone_name)) = map.territory(config.map.zone_from_folder, &sidecar)
+    else {
+        return MapOutcome::InvalidName;
+    };
+    if let Err(e) = map.check_tiles() {
+        return MapOutcome::Failed(e);
+    }
+    let fingerprint = match territory_fingerprint(config, map) {
+        Ok(fingerprint) => fingerprint,
+        Err(e) => return MapOutcome::Failed(e.to_string()),
+    };
+    debug!("{}: fingerprint {}", map.name.display(), fingerprint);
+    let cached = cache.up_to_date(&map.name, &fingerprint).filter(|_| !force);
+    if let Some(entry) = cached {
+        if let Some(sheet_entries) =
+            cached_sheet_entries(config, entry, &zone_name, &territory_number)
+        {
+            // The fingerprint covers the configuration, so the pages have the layout size
+            let outputs = entry
+                .outputs
+                .iter()
+                .map(|path| SavedPage {
+                    path: path.clone(),
+                    width: config.layout.width,
+                    height: config.layout.height,
+                })
+                .collect();
+            return MapOutcome::Skipped(RenderedTerritory {
+                outputs,
+                sheet_entries,
+            });
+        }
+    }
+
+    // Layouts go to the same subfolder of the output directory as their map
+    let output_directory = Path::new(&config.output_directory).join(&map.folder);
+    let result = fs::create_dir_all(&output_directory)
+        .map_err(|e| format!("Failed to create output directory: {}", e).into())
+        .and_then(|_| {
+            render_territory(
+                config,
+                map,
+                &sidecar,
+                &territory_number,
+                &zone_name,
+                &output_directory,
+            )
+        });
+    match result {
+        Ok(rendered) => {
+            let outputs = rendered.outputs.iter().map(|page| page.path.clone());
+            let thumbnails = save_thumbnails(config, map, &rendered.sheet_entries);
+            let stale = cache.insert(&map.name, fingerprint, outputs.collect(), thumbnails);
+            remove_stale_files(&stale);
+            MapOutcome::Rendered(rendered)
+        }
+        Err(e) => {
+            cache.remove(&map.name);
+            MapOutcome::Failed(e.to_string())
+        }
+    }
+}
+
+// A map that couldn't be processed, kept for the failure review
+pub struct Failure {
+    pub map: MapSource,
+    pub error: String,
+}
+
+// Render the layouts of every map and return the ones that failed. Territories whose
+// inputs haven't changed since the last run are skipped unless `force` is set
+pub fn process_images(
+    config: &AppConfig,
+    force: bool,
+) -> Result<Vec<Failure>, Box<dyn std::error::Error>> {
     let output_directory = Path::new(&config.output_directory);
     fs::create_dir_all(output_directory)
         .map_err(|e| format!("Failed to create output directory: {}", e))?;
 
+    let log_file = open_log_file(config)?;
     let maps_directory = Path::new(&config.map.maps_directory);
 
-    // Gather all entries to determine the total count
-    let entries: Vec<_> = fs::read_dir(maps_directory)?.collect();
-    let total_images = entries.len();
+    // Gather all maps to determine the total count
+    let maps = collect_maps(maps_directory)?;
+    let total_images = maps.len();
+    info!(
+        "Processing {} maps from {}{}",
+        total_images,
+        maps_directory.display(),
+        if force {
+            ", ignoring the build cache"
+        } else {
+            ""
+        }
+    );
 
     // Initialize the progress bar
     let progress_bar = ProgressBar::new(total_images as u64);
@@ -27,72 +635,286 @@ pub fn process_images(config: &AppConfig) -> Result<(), Box<dyn std::error::Erro
             .map_err(|e| format!("Failed to create progress bar: {}", e))?,
     );
     progress_bar.set_message("\rProcessing images...");
+    set_progress_bar(Some(progress_bar.clone()));
 
     let mut success_count = 0;
     let mut failure_count = 0;
+    let mut skipped_count = 0;
+    let mut cache = BuildCache::load(output_directory);
+    let mut sheet_entries = Vec::new();
+    let mut report = Vec::new();
+    let mut failures = Vec::new();
     let start_time = Instant::now();
 
-    for entry in
-        fs::read_dir(maps_directory).map_err(|e| format!("Failed to read directory: {}", e))?
-    {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "png") {
-                if let Some(filename) = path.file_stem().and_then(|f| f.to_str()) {
-                    let parts: Vec<&str> = filename.splitn(2, '-').collect();
-                    if parts.len() == 2 {
-                        let territory_number = parts[0];
-                        let zone_name = title_case(&parts[1].replace("-", " "));
-
-                        let result = (|| {
-                            let mut layout = create_layout(&config, &zone_name, territory_number)?;
-                            add_map_image(
-                                &mut layout,
-                                path.to_str().unwrap(),
-                                config.layout.margin,
-                                config.map.crop,
-                            )?;
-
-                            let output_filename = format!(
-                                "{}-{}.png",
-                                territory_number,
-                                zone_name.replace(" ", "-").to_lowercase()
-                            );
-                            let output_path = output_directory.join(output_filename);
-                            layout.save(output_path)?;
-                            Ok::<(), Box<dyn std::error::Error>>(())
-                        })();
-                        match result {
-                            Ok(_) => success_count += 1,
-                            Err(e) => {
-                                eprintln!(
-                                    "\r{}",
-                                    format!("Failed to process {}: {}", filename, e)
-                                        .with(Color::Red)
-                                );
-                                failure_count += 1;
-                            }
-                        }
-                    } else {
-                        eprintln!(
-                            "\r{}",
-                            format!("Invalid filename format: {}", filename).with(Color::Red)
-                        );
-                    }
-                }
+    for map in &maps {
+        let map_start_time = Instant::now();
+        let mut outcome = process_map(config, map, force, &mut cache);
+        let map_elapsed = map_start_time.elapsed();
+        report.push(ReportEntry::new(map, &outcome, map_elapsed));
+        match &mut outcome {
+            MapOutcome::Rendered(rendered) => {
+                let outputs: Vec<String> = rendered
+                    .outputs
+                    .iter()
+                    .map(|page| page.path.display().to_string())
+                    .collect();
+                info!(
+                    "Rendered {} in {:.2?} -> {}",
+                    map.name.display(),
+                    map_elapsed,
+                    outputs.join(", ")
+                );
+                sheet_entries.append(&mut rendered.sheet_entries);
+                success_count += 1;
+            }
+            MapOutcome::Skipped(rendered) => {
+                info!("Skipped {}, up to date", map.name.display());
+                sheet_entries.append(&mut rendered.sheet_entries);
+                skipped_count += 1;
+            }
+            MapOutcome::Failed(e) => {
+                error!("Failed to process {}: {}", map.name.display(), e);
+                failures.push(Failure {
+                    map: map.clone(),
+                    error: e.clone(),
+                });
+                failure_count += 1;
+            }
+            MapOutcome::InvalidName => {
+                warn!("Invalid filename format: {}", map.name.display());
+                failures.push(Failure {
+                    map: map.clone(),
+                    error: "Invalid filename format".to_string(),
+                });
+                failure_count += 1;
             }
         }
         progress_bar.inc(1);
     }
     let elapsed = start_time.elapsed();
+    let names: Vec<&Path> = maps.iter().map(|map| map.name.as_path()).collect();
+    remove_stale_files(&cache.retain(&names));
 
     progress_bar.finish_with_message("Processing complete");
+    set_progress_bar(None);
+    info!(
+        "Finished in {:.2?}: {} rendered, {} failed, {} skipped",
+        elapsed, success_count, failure_count, skipped_count
+    );
+    cache.save(output_directory)?;
+
+    let contact_sheet = create_contact_sheet(config, &sheet_entries)?;
+    let report = write_report(config, &report)?;
 
     // Display summary
     println!("\n\n\r\t SUMMARY:");
     println!("\r\t Success: {}", success_count);
     println!("\r\t Failures: {}", failure_count);
-    println!("\r\t Time taken: {:.2?}\n", elapsed);
+    println!("\r\t Skipped (up to date): {}", skipped_count);
+    println!("\r\t Time taken: {:.2?}", elapsed);
+    if let Some(path) = contact_sheet {
+        println!("\r\t Contact sheet: {}", path.display());
+    }
+    if let Some(path) = report {
+        println!("\r\t Report: {}", path.display());
+    }
+    if let Some(path) = log_file {
+        println!("\r\t Log: {}", path.display());
+    }
+    println!();
 
-    Ok(())
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{ContactSheetConfig, MapPlacement, OutputFormat};
+    use std::env;
+
+    // Empty files named like map tiles in a fresh temporary directory
+    fn maps_directory(name: &str, files: &[&str]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("buggy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for file in files {
+            let path = directory.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn splits_tile_suffixes() {
+        assert_eq!(split_tile_suffix("12-zone.2"), ("12-zone", 2));
+        assert_eq!(split_tile_suffix("12-zone"), ("12-zone", 0));
+        assert_eq!(split_tile_suffix("12-zone.v2"), ("12-zone.v2", 0));
+    }
+
+    #[test]
+    fn groups_tiles_by_map() {
+        let directory = maps_directory(
+            "group",
+            &["12-zone.2.png", "12-zone.1.png", "7-zone.jpg", "notes.txt"],
+        );
+        let maps = collect_maps(&directory).unwrap();
+        let names: Vec<_> = maps.iter().map(|map| map.name.clone()).collect();
+        assert_eq!(
+            names,
+            vec![PathBuf::from("12-zone"), PathBuf::from("7-zone")]
+        );
+        assert_eq!(
+            maps[0].tiles,
+            vec![
+                directory.join("12-zone.1.png"),
+                directory.join("12-zone.2.png")
+            ]
+        );
+        assert_eq!(maps[0].path, directory.join("12-zone.png"));
+        assert!(maps.iter().all(|map| map.check_tiles().is_ok()));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    fn map_source(folder: &str, name: &str) -> MapSource {
+        let path = Path::new("maps").join(folder).join(format!("{}.png", name));
+        MapSource {
+            path: path.clone(),
+            tiles: vec![path],
+            folder: PathBuf::from(folder),
+            name: Path::new(folder).join(name),
+        }
+    }
+
+    #[test]
+    fn parses_territory_from_filename() {
+        let map = map_source("", "12-casal-monastero");
+        let expected = Some(("12".to_string(), "Casal Monastero".to_string()));
+        assert_eq!(map.parsed_territory(false), expected);
+        // Without a subfolder the zone still comes from the filename
+        assert_eq!(map.parsed_territory(true), expected);
+        assert_eq!(map_source("", "12").parsed_territory(false), None);
+    }
+
+    #[test]
+    fn parses_zone_from_folder() {
+        let map = map_source("casal-monastero", "12");
+        let expected = Some(("12".to_string(), "Casal Monastero".to_string()));
+        assert_eq!(map.parsed_territory(true), expected);
+        assert_eq!(
+            map_source("casal-monastero", "12-north").parsed_territory(true),
+            expected
+        );
+        assert_eq!(map.parsed_territory(false), None);
+    }
+
+    #[test]
+    fn sidecar_overrides_the_parsed_territory() {
+        let map = map_source("", "12-casal-monastero");
+        let mut sidecar = MapSidecar {
+            zone_name: Some("Monastero Nord".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            map.territory(false, &sidecar),
+            Some(("12".to_string(), "Monastero Nord".to_string()))
+        );
+        sidecar.territory_number = Some("12a".to_string());
+        assert_eq!(
+            map.territory(false, &sidecar),
+            Some(("12a".to_string(), "Monastero Nord".to_string()))
+        );
+    }
+
+    #[test]
+    fn sidecar_names_a_map_without_a_valid_filename() {
+        let map = map_source("", "scan");
+        let mut sidecar = MapSidecar {
+            territory_number: Some("7".to_string()),
+            ..Default::default()
+        };
+        // Half a territory is still not enough
+        assert_eq!(map.territory(false, &sidecar), None);
+        sidecar.zone_name = Some("Torraccia".to_string());
+        assert_eq!(
+            map.territory(false, &sidecar),
+            Some(("7".to_string(), "Torraccia".to_string()))
+        );
+    }
+
+    #[test]
+    fn finds_maps_in_subfolders() {
+        let directory = maps_directory("folders", &["casal-monastero/12.png", "7-zone.png"]);
+        let maps = collect_maps(&directory).unwrap();
+        let names: Vec<_> = maps.iter().map(|map| map.name.clone()).collect();
+        assert_eq!(
+            names,
+            vec![PathBuf::from("7-zone"), PathBuf::from("casal-monastero/12")]
+        );
+        assert_eq!(maps[1].folder, PathBuf::from("casal-monastero"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn svg_layouts_with_a_contact_sheet_are_cached() {
+        let directory = maps_directory("cache", &[]);
+        let maps_path = directory.join("maps");
+        fs::create_dir_all(&maps_path).unwrap();
+        RgbImage::from_pixel(400, 300, image::Rgb([200, 220, 200]))
+            .save(maps_path.join("12-zone.png"))
+            .unwrap();
+        let mut config = AppConfig::default();
+        config.map.maps_directory = maps_path.display().to_string();
+        config.output_directory = directory.join("layouts").display().to_string();
+        config.output_format = OutputFormat::Svg;
+        config.contact_sheet = Some(ContactSheetConfig {
+            output_filename: "contact-sheet.png".to_string(),
+            columns: 4,
+            thumbnail_width: 100,
+            font_size: 12.0,
+            label: "<territory_number>".to_string(),
+        });
+
+        let maps = collect_maps(&maps_path).unwrap();
+        let mut cache = BuildCache::default();
+        match process_map(&config, &maps[0], false, &mut cache) {
+            MapOutcome::Rendered(rendered) => assert_eq!(rendered.outputs.len(), 1),
+            MapOutcome::Failed(e) => panic!("{}", e),
+            _ => panic!("expected the territory to be rendered"),
+        }
+        match process_map(&config, &maps[0], false, &mut cache) {
+            MapOutcome::Skipped(rendered) => {
+                assert_eq!(rendered.sheet_entries.len(), 1);
+                assert_eq!(rendered.sheet_entries[0].thumbnail.width(), 100);
+            }
+            _ => panic!("expected the territory to be up to date"),
+        }
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn preview_ignores_supersampling() {
+        let directory = maps_directory("preview", &[]);
+        RgbImage::from_pixel(400, 300, image::Rgb([200, 220, 200]))
+            .save(directory.join("12-zone.png"))
+            .unwrap();
+        let maps = collect_maps(&directory).unwrap();
+        let mut config = AppConfig::default();
+        // Kept at its original size, so a supersampled map would be drawn larger
+        config.map.placement = MapPlacement::None;
+        let preview = preview_layout(&config, &maps[0]).unwrap();
+        config.layout.supersample = 3;
+        assert!(preview_layout(&config, &maps[0]).unwrap() == preview);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn rejects_two_files_for_the_same_tile() {
+        let directory = maps_directory("duplicate", &["12-zone.png", "12-zone.jpg"]);
+        let maps = collect_maps(&directory).unwrap();
+        assert_eq!(maps.len(), 1);
+        let error = maps[0].check_tiles().unwrap_err();
+        assert!(error.contains("12-zone.jpg") && error.contains("12-zone.png"));
+        fs::remove_dir_all(directory).unwrap();
+    }
 }
@@ -0,0 +1,145 @@
+use crate::configuration::{AppConfig, MapSidecar};
+use crate::image_processing::{pixel_scale, text_variables};
+use crate::text_processing::replace_variables;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::draw_filled_rect_mut;
+use imageproc::rect::Rect;
+use qrcode::{Color, QrCode};
+
+// Modules of white border required around the code by the QR specification
+const QUIET_ZONE: u32 = 4;
+
+// Percent-encode a value so it can be placed inside a URL
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn add_qr_code(
+    layout: &mut RgbImage,
+    config: &AppConfig,
+    name: &str,
+    number: &str,
+    sidecar: &MapSidecar,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let qr_code = match &config.layout.qr_code {
+        Some(qr_code) => qr_code,
+        None => return Ok(()),
+    };
+
+    let mut variables = text_variables(name, number);
+    variables.extend(
+        sidecar
+            .variables
+            .iter()
+            .map(|(var, value)| (var.clone(), value.clone())),
+    );
+    let variables: Vec<_> = variables
+        .into_iter()
+        .map(|(var, value)| (var, url_encode(&value)))
+        .collect();
+    let url = replace_variables(&qr_code.url, &variables);
+
+    let code = QrCode::new(url.as_bytes())
+        .map_err(|e| format!("Failed to create QR code for {} - {}", url, e))?;
+
+    // Use a whole number of pixels per module so the code stays crisp. Supersampled
+    // layouts get the module size of the final layout times the factor, so the modules
+    // shrink back to whole pixels
+    let modules = code.width() as u32 + 2 * QUIET_ZONE;
+    let scale = pixel_scale(config);
+    let unscaled_size = qr_code.size / scale;
+    if unscaled_size < modules {
+        return Err(format!(
+            "QR code size {} is too small for {} - it needs at least {} pixels",
+            unscaled_size, url, modules
+        )
+        .into());
+    }
+    let module_size = unscaled_size / modules * scale;
+    let size = module_size * modules;
+
+    let background = Rect::at(qr_code.x as i32, qr_code.y as i32).of_size(size, size);
+    draw_filled_rect_mut(layout, background, Rgb([255u8, 255u8, 255u8]));
+
+    for (index, color) in code.to_colors().into_iter().enumerate() {
+        if color != Color::Dark {
+            continue;
+        }
+        let column = index as u32 % code.width() as u32 + QUIET_ZONE;
+        let row = index as u32 / code.width() as u32 + QUIET_ZONE;
+        let module = Rect::at(
+            (qr_code.x + column * module_size) as i32,
+            (qr_code.y + row * module_size) as i32,
+        )
+        .of_size(module_size, module_size);
+        draw_filled_rect_mut(layout, module, Rgb([0u8, 0u8, 0u8]));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::QrCodeConfig;
+
+    // Size of the QR code drawn on a white layout of `config`
+    fn drawn_size(config: &AppConfig) -> u32 {
+        let mut layout = RgbImage::from_pixel(
+            config.layout.width,
+            config.layout.height,
+            Rgb([255, 255, 255]),
+        );
+        add_qr_code(&mut layout, config, "Zone", "12", &MapSidecar::default()).unwrap();
+        let dark: Vec<_> = layout
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] == 0)
+            .map(|(x, _, _)| x)
+            .collect();
+        dark.iter().max().unwrap() - dark.iter().min().unwrap() + 1
+    }
+
+    #[test]
+    fn supersampled_modules_shrink_to_whole_pixels() {
+        let mut config = AppConfig::default();
+        config.layout.qr_code = Some(QrCodeConfig {
+            url: "https://example.com/territory/<territory_number>".to_string(),
+            size: 150,
+            x: 10,
+            y: 10,
+        });
+        let plain = drawn_size(&config);
+        config.layout.supersample = 3;
+        let supersampled = drawn_size(&config.scaled(3));
+        assert_eq!(supersampled, plain * 3);
+    }
+
+    #[test]
+    fn rejects_a_size_smaller_than_the_modules() {
+        let mut config = AppConfig::default();
+        config.layout.qr_code = Some(QrCodeConfig {
+            url: "https://example.com".to_string(),
+            size: 20,
+            x: 0,
+            y: 0,
+        });
+        let mut layout = RgbImage::new(100, 100);
+        let error = add_qr_code(&mut layout, &config, "Zone", "12", &MapSidecar::default());
+        assert!(error.unwrap_err().to_string().contains("too small"));
+    }
+
+    #[test]
+    fn url_encodes_reserved_characters() {
+        assert_eq!(url_encode("Casal-Monastero_1.~"), "Casal-Monastero_1.~");
+        assert_eq!(url_encode("a b/c?d=e&f"), "a%20b%2Fc%3Fd%3De%26f");
+        assert_eq!(url_encode("città"), "citt%C3%A0");
+    }
+}
@@ -0,0 +1,177 @@
+use crate::configuration::{AppConfig, ReportFormat};
+use crate::process_images::{MapOutcome, MapSource};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Serialize)]
+struct ReportOutput {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+}
+
+// What happened to one map during a run
+#[derive(Debug, Serialize)]
+pub struct ReportEntry {
+    map: PathBuf,
+    inputs: Vec<PathBuf>,
+    // `rendered`, `skipped` (up to date), `failed` or `invalid_name`
+    status: &'static str,
+    error: Option<String>,
+    outputs: Vec<ReportOutput>,
+    render_time_ms: u64,
+}
+
+impl ReportEntry {
+    pub fn new(map: &MapSource, outcome: &MapOutcome, render_time: Duration) -> Self {
+        let (status, error, pages) = match outcome {
+            MapOutcome::Rendered(rendered) => ("rendered", None, rendered.outputs.as_slice()),
+            MapOutcome::Skipped(rendered) => ("skipped", None, rendered.outputs.as_slice()),
+            MapOutcome::Failed(e) => ("failed", Some(e.clone()), &[][..]),
+            MapOutcome::InvalidName => (
+                "invalid_name",
+                Some("Invalid filename format".to_string()),
+                &[][..],
+            ),
+        };
+        ReportEntry {
+            map: map.name.clone(),
+            inputs: map.tiles.clone(),
+            status,
+            error,
+            outputs: pages
+                .iter()
+                .map(|page| ReportOutput {
+                    path: page.path.clone(),
+                    width: page.width,
+                    height: page.height,
+                })
+                .collect(),
+            render_time_ms: render_time.as_millis() as u64,
+        }
+    }
+}
+
+// Quote a CSV field when it holds a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv(entries: &[ReportEntry]) -> Result<String, std::fmt::Error> {
+    let mut csv = String::new();
+    writeln!(
+        csv,
+        "map,inputs,status,error,output,width,height,render_time_ms"
+    )?;
+    for entry in entries {
+        let inputs: Vec<String> = entry
+            .inputs
+            .iter()
+            .map(|input| input.display().to_string())
+            .collect();
+        let fields = [
+            entry.map.display().to_string(),
+            inputs.join(";"),
+            entry.status.to_string(),
+            entry.error.clone().unwrap_or_default(),
+        ]
+        .map(|field| csv_field(&field))
+        .join(",");
+        if entry.outputs.is_empty() {
+            writeln!(csv, "{},,,,{}", fields, entry.render_time_ms)?;
+        }
+        for output in &entry.outputs {
+            writeln!(
+                csv,
+                "{},{},{},{},{}",
+                fields,
+                csv_field(&output.path.display().to_string()),
+                output.width,
+                output.height,
+                entry.render_time_ms
+            )?;
+        }
+    }
+    Ok(csv)
+}
+
+// Write the report of a run to the output directory, if one is configured
+pub fn write_report(
+    config: &AppConfig,
+    entries: &[ReportEntry],
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let Some(report) = &config.report else {
+        return Ok(None);
+    };
+    let content = match report.format {
+        ReportFormat::Json => serde_json::to_string_pretty(entries)?,
+        ReportFormat::Csv => to_csv(entries)?,
+    };
+    let path = Path::new(&config.output_directory).join(&report.output_filename);
+    fs::write(&path, content)
+        .map_err(|e| format!("Failed to write report {} - {}", path.display(), e))?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_images::{RenderedTerritory, SavedPage};
+
+    fn map_source() -> MapSource {
+        MapSource {
+            path: PathBuf::from("maps/12-zone.png"),
+            tiles: vec![
+                PathBuf::from("maps/12-zone.1.png"),
+                PathBuf::from("maps/12-zone.2.png"),
+            ],
+            folder: PathBuf::new(),
+            name: PathBuf::from("12-zone"),
+        }
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("12-zone"), "12-zone");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_has_a_row_per_page() {
+        let rendered = MapOutcome::Rendered(RenderedTerritory {
+            outputs: (1..=2)
+                .map(|page| SavedPage {
+                    path: PathBuf::from(format!("12-zone-p{}.png", page)),
+                    width: 1000,
+                    height: 707,
+                })
+                .collect(),
+            sheet_entries: Vec::new(),
+        });
+        let failed = MapOutcome::Failed("Bad crop, too large".to_string());
+        let entries = [
+            ReportEntry::new(&map_source(), &rendered, Duration::from_millis(1500)),
+            ReportEntry::new(&map_source(), &failed, Duration::from_millis(3)),
+        ];
+        let inputs = "maps/12-zone.1.png;maps/12-zone.2.png";
+        assert_eq!(
+            to_csv(&entries).unwrap(),
+            format!(
+                "map,inputs,status,error,output,width,height,render_time_ms\n\
+                 12-zone,{0},rendered,,12-zone-p1.png,1000,707,1500\n\
+                 12-zone,{0},rendered,,12-zone-p2.png,1000,707,1500\n\
+                 12-zone,{0},failed,\"Bad crop, too large\",,,,3\n",
+                inputs
+            )
+        );
+    }
+}
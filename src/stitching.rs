@@ -0,0 +1,255 @@
+use image::imageops::FilterType;
+use image::{GrayImage, RgbaImage};
+use imageproc::template_matching::{find_extremes, match_template, MatchTemplateMethod};
+
+// Tiles are matched on copies scaled down to at most this size
+const MATCH_SIZE: u32 = 256;
+// Refine the coarse match within this many full-size pixels
+const REFINE_RADIUS: u32 = 4;
+// Largest normalized match error of tiles that really overlap; above it the best
+// match is only the least different place, not the same part of the map
+const MAX_MATCH_ERROR: f32 = 0.02;
+
+// Best location found for one of the patches taken from a tile
+struct PatchMatch {
+    offset: (i64, i64),
+    patch: (u32, u32),
+    error: f32,
+}
+
+fn to_gray(image: &RgbaImage) -> GrayImage {
+    image::DynamicImage::ImageRgba8(image.clone()).to_luma8()
+}
+
+// Find `patch` in `image`, returning its top-left position and the match error
+fn find_patch(image: &GrayImage, patch: &GrayImage) -> Option<((u32, u32), f32)> {
+    if patch.width() > image.width() || patch.height() > image.height() {
+        return None;
+    }
+    let errors = match_template(
+        image,
+        patch,
+        MatchTemplateMethod::SumOfSquaredErrorsNormalized,
+    );
+    let extremes = find_extremes(&errors);
+    Some((extremes.min_value_location, extremes.min_value))
+}
+
+// Position of `tile` relative to `previous`, found by locating parts of `tile` in `previous`
+fn find_offset(previous: &RgbaImage, tile: &RgbaImage) -> Result<(i64, i64), String> {
+    let scale = f32::min(
+        1.0,
+        MATCH_SIZE as f32 / previous.width().max(previous.height()) as f32,
+    );
+    let resize = |image: &GrayImage| {
+        image::imageops::resize(
+            image,
+            ((image.width() as f32 * scale).round() as u32).max(1),
+            ((image.height() as f32 * scale).round() as u32).max(1),
+            FilterType::Triangle,
+        )
+    };
+    let (previous_gray, tile_gray) = (to_gray(previous), to_gray(tile));
+    let (previous_small, tile_small) = (resize(&previous_gray), resize(&tile_gray));
+
+    // The overlapping part of the tile is near one of its edges, so try a patch
+    // from each side and keep the one that matches best
+    let (small_w, small_h) = tile_small.dimensions();
+    let (patch_w, patch_h) = ((small_w / 4).max(1), (small_h / 4).max(1));
+    let candidates = [
+        (0, (small_h - patch_h) / 2),
+        (small_w - patch_w, (small_h - patch_h) / 2),
+        ((small_w - patch_w) / 2, 0),
+        ((small_w - patch_w) / 2, small_h - patch_h),
+        ((small_w - patch_w) / 2, (small_h - patch_h) / 2),
+    ];
+    let mut best: Option<PatchMatch> = None;
+    for (patch_x, patch_y) in candidates {
+        let patch =
+            image::imageops::crop_imm(&tile_small, patch_x, patch_y, patch_w, patch_h).to_image();
+        if let Some(((found_x, found_y), error)) = find_patch(&previous_small, &patch) {
+            if best.as_ref().is_none_or(|best| error < best.error) {
+                best = Some(PatchMatch {
+                    offset: (
+                        found_x as i64 - patch_x as i64,
+                        found_y as i64 - patch_y as i64,
+                    ),
+                    patch: (patch_x, patch_y),
+                    error,
+                });
+            }
+        }
+    }
+    let PatchMatch {
+        offset: (small_x, small_y),
+        patch: (patch_x, patch_y),
+        error,
+    } = best.ok_or("Failed to match tile, it is larger than the previous one")?;
+    if error > MAX_MATCH_ERROR {
+        return Err("Failed to match tile, it doesn't seem to overlap the previous one".into());
+    }
+
+    // Refine at full size around the scaled up coarse offset
+    let to_full = |value: u32| (value as f32 / scale).round() as u32;
+    let (full_patch_x, full_patch_y) = (to_full(patch_x), to_full(patch_y));
+    let (full_patch_w, full_patch_h) = (
+        to_full(patch_w).min(tile.width() - full_patch_x),
+        to_full(patch_h).min(tile.height() - full_patch_y),
+    );
+    let patch = image::imageops::crop_imm(
+        &tile_gray,
+        full_patch_x,
+        full_patch_y,
+        full_patch_w,
+        full_patch_h,
+    )
+    .to_image();
+    let radius = (1.0 / scale).ceil() as i64 + REFINE_RADIUS as i64;
+    let coarse_x = (small_x as f32 / scale).round() as i64 + full_patch_x as i64;
+    let coarse_y = (small_y as f32 / scale).round() as i64 + full_patch_y as i64;
+    let search_x = (coarse_x - radius).clamp(0, previous.width() as i64) as u32;
+    let search_y = (coarse_y - radius).clamp(0, previous.height() as i64) as u32;
+    let search_w = (full_patch_w + 2 * radius as u32).min(previous.width() - search_x);
+    let search_h = (full_patch_h + 2 * radius as u32).min(previous.height() - search_y);
+    let search = image::imageops::crop_imm(&previous_gray, search_x, search_y, search_w, search_h)
+        .to_image();
+
+    match find_patch(&search, &patch) {
+        Some(((found_x, found_y), _)) => Ok((
+            (search_x + found_x) as i64 - full_patch_x as i64,
+            (search_y + found_y) as i64 - full_patch_y as i64,
+        )),
+        None => Ok((
+            coarse_x - full_patch_x as i64,
+            coarse_y - full_patch_y as i64,
+        )),
+    }
+}
+
+// Combine the tiles of one territory into a single map. Each tile is placed at
+// its configured offset or, without offsets, aligned with the previous tile
+pub fn stitch_tiles(
+    tiles: &[RgbaImage],
+    offsets: Option<&[[i64; 2]]>,
+) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    let positions: Vec<(i64, i64)> = match offsets {
+        Some(offsets) => {
+            if offsets.len() != tiles.len() {
+                return Err(format!(
+                    "Expected {} tile offsets but {} are configured",
+                    tiles.len(),
+                    offsets.len()
+                )
+                .into());
+            }
+            offsets.iter().map(|[x, y]| (*x, *y)).collect()
+        }
+        None => {
+            let mut positions = vec![(0, 0)];
+            for pair in tiles.windows(2) {
+                let (previous_x, previous_y) = positions[positions.len() - 1];
+                let (offset_x, offset_y) = find_offset(&pair[0], &pair[1]).map_err(|e| {
+                    format!(
+                        "Tile {} - {}. Set tile_offsets in the map's sidecar to place the tiles",
+                        positions.len() + 1,
+                        e
+                    )
+                })?;
+                positions.push((previous_x + offset_x, previous_y + offset_y));
+            }
+            positions
+        }
+    };
+
+    let min_x = positions.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let min_y = positions.iter().map(|(_, y)| *y).min().unwrap_or(0);
+    let max_x = tiles
+        .iter()
+        .zip(&positions)
+        .map(|(tile, (x, _))| x + tile.width() as i64)
+        .max()
+        .unwrap_or(0);
+    let max_y = tiles
+        .iter()
+        .zip(&positions)
+        .map(|(tile, (_, y))| y + tile.height() as i64)
+        .max()
+        .unwrap_or(0);
+
+    let mut stitched = RgbaImage::new((max_x - min_x) as u32, (max_y - min_y) as u32);
+    for (tile, (x, y)) in tiles.iter().zip(&positions) {
+        image::imageops::overlay(&mut stitched, tile, x - min_x, y - min_y);
+    }
+    Ok(stitched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    // A map-like image of `block` pixel squares in pseudo-random colors
+    fn blocks(width: u32, height: u32, block: u32, seed: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let mut hash = (x / block)
+                .wrapping_mul(73_856_093)
+                .wrapping_add((y / block).wrapping_mul(19_349_663))
+                .wrapping_add(seed.wrapping_mul(83_492_791));
+            hash ^= hash >> 13;
+            hash = hash.wrapping_mul(0x5bd1_e995);
+            hash ^= hash >> 15;
+            Rgba([hash as u8, (hash >> 8) as u8, (hash >> 16) as u8, 255])
+        })
+    }
+
+    fn texture(width: u32, height: u32, seed: u32) -> RgbaImage {
+        blocks(width, height, 4, seed)
+    }
+
+    fn crop(image: &RgbaImage, x: u32, y: u32, w: u32, h: u32) -> RgbaImage {
+        image::imageops::crop_imm(image, x, y, w, h).to_image()
+    }
+
+    #[test]
+    fn finds_the_offset_of_overlapping_tiles() {
+        let map = texture(170, 110, 1);
+        let left = crop(&map, 0, 0, 100, 100);
+        let right = crop(&map, 60, 10, 100, 100);
+        assert_eq!(find_offset(&left, &right), Ok((60, 10)));
+
+        let stitched = stitch_tiles(&[left, right], None).unwrap();
+        assert_eq!(stitched.dimensions(), (160, 110));
+        assert_eq!(stitched.get_pixel(130, 50), map.get_pixel(130, 50));
+    }
+
+    #[test]
+    fn finds_the_offset_of_tiles_larger_than_the_match_size() {
+        // Matched on scaled down copies first, then refined at full size
+        let map = blocks(850, 440, 12, 3);
+        let left = crop(&map, 0, 0, 600, 400);
+        let right = crop(&map, 250, 37, 600, 400);
+        assert!(left.width() > MATCH_SIZE);
+        assert_eq!(find_offset(&left, &right), Ok((250, 37)));
+
+        let stitched = stitch_tiles(&[left, right], None).unwrap();
+        assert_eq!(stitched.dimensions(), (850, 437));
+        assert_eq!(stitched.get_pixel(800, 300), map.get_pixel(800, 300));
+    }
+
+    #[test]
+    fn rejects_tiles_that_do_not_overlap() {
+        let first = texture(100, 100, 1);
+        let second = texture(100, 100, 2);
+        assert!(find_offset(&first, &second).is_err());
+        let error = stitch_tiles(&[first, second], None).unwrap_err();
+        assert!(error.to_string().contains("tile_offsets"));
+    }
+
+    #[test]
+    fn places_tiles_at_configured_offsets() {
+        let tiles = [texture(100, 80, 1), texture(100, 80, 2)];
+        let stitched = stitch_tiles(&tiles, Some(&[[0, 0], [60, -10]])).unwrap();
+        assert_eq!(stitched.dimensions(), (160, 90));
+        assert!(stitch_tiles(&tiles, Some(&[[0, 0]])).is_err());
+    }
+}
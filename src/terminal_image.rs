@@ -0,0 +1,295 @@
+use crate::configuration::TerminalGraphics;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crossterm::terminal;
+use image::imageops::FilterType;
+use image::{ImageFormat, RgbImage};
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, Cursor, Write};
+
+// Size of a character cell in pixels when the terminal doesn't report it
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+// Largest piece of base64 data in one kitty graphics escape sequence
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+// Guess from the environment which graphics the terminal understands. Terminals
+// don't advertise kitty graphics, so this only recognizes the common ones
+fn detect_graphics() -> TerminalGraphics {
+    let term = env::var("TERM").unwrap_or_default().to_lowercase();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default().to_lowercase();
+    if env::var_os("KITTY_WINDOW_ID").is_some()
+        || term.contains("kitty")
+        || term.contains("ghostty")
+        || program == "wezterm"
+        || program == "ghostty"
+    {
+        TerminalGraphics::Kitty
+    } else if term.contains("sixel")
+        || term.starts_with("foot")
+        || term.starts_with("mlterm")
+        || program == "mlterm"
+    {
+        TerminalGraphics::Sixel
+    } else {
+        TerminalGraphics::HalfBlocks
+    }
+}
+
+// Columns and rows of the terminal, assuming 80x24 when it doesn't report them
+pub fn terminal_cells() -> (u16, u16) {
+    terminal::size()
+        .ok()
+        .filter(|(columns, rows)| *columns > 0 && *rows > 0)
+        .unwrap_or((80, 24))
+}
+
+// Terminal size in columns and rows, and the size of a cell in pixels
+fn terminal_size() -> ((u16, u16), (u32, u32)) {
+    let (columns, rows) = terminal_cells();
+    let cell_size = terminal::window_size()
+        .ok()
+        .filter(|size| size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0)
+        .map(|size| {
+            (
+                (size.width / size.columns) as u32,
+                (size.height / size.rows) as u32,
+            )
+        })
+        .unwrap_or(DEFAULT_CELL_SIZE);
+    ((columns, rows), cell_size)
+}
+
+// Scale the image down to fit in `width` x `height` pixels, keeping its proportions
+pub fn fit(image: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let scale = f32::min(
+        width as f32 / image.width() as f32,
+        height as f32 / image.height() as f32,
+    )
+    .min(1.0);
+    let new_w = ((image.width() as f32 * scale).round() as u32).max(1);
+    let new_h = ((image.height() as f32 * scale).round() as u32).max(1);
+    if (new_w, new_h) == image.dimensions() {
+        return image.clone();
+    }
+    image::imageops::resize(image, new_w, new_h, FilterType::Triangle)
+}
+
+// Kitty graphics protocol: the image sent as PNG, in base64 chunks
+fn kitty(image: &RgbImage, columns: u16, rows: u16) -> Result<String, Box<dyn std::error::Error>> {
+    let mut png_data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png_data), ImageFormat::Png)?;
+    let data = BASE64.encode(png_data);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+
+    let mut output = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = if index + 1 < chunks.len() { 1 } else { 0 };
+        if index == 0 {
+            write!(
+                output,
+                "\x1b_Gf=100,a=T,c={},r={},m={};",
+                columns, rows, more
+            )?;
+        } else {
+            write!(output, "\x1b_Gm={};", more)?;
+        }
+        output.push_str(std::str::from_utf8(chunk)?);
+        output.push_str("\x1b\\");
+    }
+    Ok(output)
+}
+
+// Index of a color in the 6x6x6 color cube used for sixel output
+fn cube_index(pixel: &image::Rgb<u8>) -> usize {
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])
+}
+
+// Sixel graphics: the image reduced to a 216 color cube, drawn in bands of six rows
+fn sixel(image: &RgbImage) -> Result<String, std::fmt::Error> {
+    let (width, height) = image.dimensions();
+    let mut output = String::new();
+    write!(output, "\x1bPq\"1;1;{};{}", width, height)?;
+    for index in 0..216 {
+        let percent = |level: usize| level * 100 / 5;
+        write!(
+            output,
+            "#{};2;{};{};{}",
+            index,
+            percent(index / 36),
+            percent(index / 6 % 6),
+            percent(index % 6)
+        )?;
+    }
+
+    let indices: Vec<usize> = image.pixels().map(cube_index).collect();
+    for band in (0..height).step_by(6) {
+        let band_rows = (height - band).min(6);
+        // Bits of the six rows set for each color and column of the band
+        let mut columns = vec![[0u8; 216]; width as usize];
+        let mut used = [false; 216];
+        for row in 0..band_rows {
+            for x in 0..width as usize {
+                let color = indices[(band + row) as usize * width as usize + x];
+                columns[x][color] |= 1 << row;
+                used[color] = true;
+            }
+        }
+
+        for color in (0..216).filter(|color| used[*color]) {
+            write!(output, "#{}", color)?;
+            // Run-length encode repeated sixels
+            let mut x = 0;
+            while x < columns.len() {
+                let bits = columns[x][color];
+                let mut run = 1;
+                while x + run < columns.len() && columns[x + run][color] == bits {
+                    run += 1;
+                }
+                let sixel = (63 + bits) as char;
+                if run > 3 {
+                    write!(output, "!{}{}", run, sixel)?;
+                } else {
+                    for _ in 0..run {
+                        output.push(sixel);
+                    }
+                }
+                x += run;
+            }
+            output.push('$');
+        }
+        output.push('-');
+    }
+    output.push_str("\x1b\\");
+    Ok(output)
+}
+
+// Unicode upper half blocks, each showing two pixels with the foreground and
+// background colors, for terminals without graphics
+fn half_blocks(image: &RgbImage) -> Result<String, std::fmt::Error> {
+    let mut output = String::new();
+    for y in (0..image.height()).step_by(2) {
+        output.push('\r');
+        for x in 0..image.width() {
+            let top = image.get_pixel(x, y);
+            let bottom = if y + 1 < image.height() {
+                image.get_pixel(x, y + 1)
+            } else {
+                top
+            };
+            write!(
+                output,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+            )?;
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    Ok(output)
+}
+
+// Largest image in pixels that `display_image` shows without shrinking it
+pub fn display_size(graphics: TerminalGraphics, max_rows: u16) -> (u32, u32) {
+    let ((columns, rows), (cell_w, cell_h)) = terminal_size();
+    let rows = max_rows.min(rows).max(1);
+    match graphics {
+        TerminalGraphics::Auto => display_size(detect_graphics(), max_rows),
+        TerminalGraphics::Kitty | TerminalGraphics::Sixel => {
+            (columns as u32 * cell_w, rows as u32 * cell_h)
+        }
+        // Cells are about twice as tall as wide, so each half block is close to square
+        TerminalGraphics::HalfBlocks => (columns as u32, rows as u32 * 2),
+    }
+}
+
+// Draw an image at the cursor, as large as fits in the terminal width and the
+// given number of rows
+pub fn display_image(
+    image: &RgbImage,
+    graphics: TerminalGraphics,
+    max_rows: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let graphics = match graphics {
+        TerminalGraphics::Auto => detect_graphics(),
+        graphics => graphics,
+    };
+    let (max_w, max_h) = display_size(graphics, max_rows);
+    let fitted = fit(image, max_w, max_h);
+
+    let output = match graphics {
+        TerminalGraphics::Kitty => {
+            let (_, (cell_w, cell_h)) = terminal_size();
+            let image_columns = fitted.width().div_ceil(cell_w) as u16;
+            let image_rows = fitted.height().div_ceil(cell_h) as u16;
+            kitty(&fitted, image_columns, image_rows)?
+        }
+        TerminalGraphics::Sixel => sixel(&fitted)?,
+        _ => half_blocks(&fitted)?,
+    };
+
+    let mut stdout = io::stdout();
+    stdout.write_all(output.as_bytes())?;
+    writeln!(stdout, "\r")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn fits_without_enlarging() {
+        let image = RgbImage::new(200, 100);
+        assert_eq!(fit(&image, 100, 100).dimensions(), (100, 50));
+        assert_eq!(fit(&image, 400, 400).dimensions(), (200, 100));
+    }
+
+    #[test]
+    fn maps_colors_to_the_cube() {
+        assert_eq!(cube_index(&Rgb([0, 0, 0])), 0);
+        assert_eq!(cube_index(&Rgb([255, 255, 255])), 215);
+        assert_eq!(cube_index(&Rgb([255, 0, 0])), 180);
+        assert_eq!(cube_index(&Rgb([0, 0, 255])), 5);
+    }
+
+    #[test]
+    fn encodes_sixel_bands() {
+        // Seven rows need two bands, the second one with a single row
+        let image = RgbImage::from_pixel(5, 7, Rgb([255, 255, 255]));
+        let output = sixel(&image).unwrap();
+        assert!(output.starts_with("\x1bPq\"1;1;5;7#0;2;0;0;0"));
+        assert!(output.ends_with("#215!5~$-#215!5@$-\x1b\\"));
+    }
+
+    #[test]
+    fn encodes_short_sixel_runs_directly() {
+        let mut image = RgbImage::new(3, 1);
+        image.put_pixel(1, 0, Rgb([255, 255, 255]));
+        let output = sixel(&image).unwrap();
+        assert!(output.ends_with("#0@?@$#215?@?$-\x1b\\"));
+    }
+
+    #[test]
+    fn draws_two_pixels_per_half_block() {
+        let mut image = RgbImage::from_pixel(1, 3, Rgb([255, 0, 0]));
+        image.put_pixel(0, 1, Rgb([0, 0, 255]));
+        let output = half_blocks(&image).unwrap();
+        assert_eq!(
+            output,
+            "\r\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[0m\n\
+             \r\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m▀\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn splits_kitty_data_in_chunks() {
+        let image = RgbImage::from_fn(200, 200, |x, y| Rgb([x as u8, y as u8, (x ^ y) as u8]));
+        let output = kitty(&image, 10, 5).unwrap();
+        assert!(output.starts_with("\x1b_Gf=100,a=T,c=10,r=5,m=1;"));
+        assert!(output.contains("\x1b_Gm=0;"));
+        assert!(output.ends_with("\x1b\\"));
+    }
+}
@@ -1,8 +1,99 @@
 use crate::configuration::Alignment;
-use ab_glyph::{FontRef, PxScale};
-use image::{Rgb, RgbImage};
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use image::{GrayImage, Luma, Rgb, RgbImage};
 use imageproc::drawing::{draw_text_mut, text_size};
 
+// A piece of text collected instead of being drawn, to be written as vector text
+pub struct TextSpan {
+    pub text: String,
+    pub x: f32,
+    // Baseline of the text
+    pub y: f32,
+    // Size of the font's em square, the CSS `font-size`
+    pub font_size: f32,
+    pub bold: bool,
+}
+
+// Draw one run of text in a single font, or add it to `spans` when given
+#[allow(clippy::too_many_arguments)]
+fn draw_segment(
+    layout: &mut RgbImage,
+    spans: Option<&mut Vec<TextSpan>>,
+    font: &FontRef,
+    bold: bool,
+    scale: PxScale,
+    x: u32,
+    y: u32,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    match spans {
+        Some(spans) => spans.push(TextSpan {
+            text: text.to_string(),
+            x: x as f32,
+            // `draw_text_mut` puts the baseline one ascent below `y`
+            y: y as f32 + font.as_scaled(scale).ascent(),
+            font_size: scale.y * font.units_per_em().unwrap_or(1000.0) / font.height_unscaled(),
+            bold,
+        }),
+        None => draw_text_mut(
+            layout,
+            Rgb([0u8, 0u8, 0u8]),
+            x.try_into().map_err(|_| "Failed to convert x to u32")?,
+            y.try_into().map_err(|_| "Failed to convert y to u32")?,
+            scale,
+            font,
+            text,
+        ),
+    }
+    Ok(())
+}
+
+// Position, pixel scale and font of a recorded span for drawing it as raster text
+fn span_placement<'a>(
+    span: &TextSpan,
+    font_regular: &'a FontRef<'a>,
+    font_bold: &'a FontRef<'a>,
+) -> (i32, i32, PxScale, &'a FontRef<'a>) {
+    let font = if span.bold { font_bold } else { font_regular };
+    let scale = PxScale::from(
+        span.font_size * font.height_unscaled() / font.units_per_em().unwrap_or(1000.0),
+    );
+    let top = span.y - font.as_scaled(scale).ascent();
+    (span.x.round() as i32, top.round() as i32, scale, font)
+}
+
+// Draw recorded spans in black, as `process_text` would have drawn them
+pub fn draw_spans(
+    layout: &mut RgbImage,
+    spans: &[TextSpan],
+    font_regular: &FontRef,
+    font_bold: &FontRef,
+) {
+    for span in spans {
+        let (x, y, scale, font) = span_placement(span, font_regular, font_bold);
+        draw_text_mut(layout, Rgb([0u8, 0u8, 0u8]), x, y, scale, font, &span.text);
+    }
+}
+
+// Coverage of every pixel by the recorded spans, 255 where fully inside a glyph
+pub fn text_mask(
+    spans: &[TextSpan],
+    width: u32,
+    height: u32,
+    font_regular: &FontRef,
+    font_bold: &FontRef,
+) -> GrayImage {
+    let mut mask = GrayImage::new(width, height);
+    for span in spans {
+        let (x, y, scale, font) = span_placement(span, font_regular, font_bold);
+        draw_text_mut(&mut mask, Luma([255u8]), x, y, scale, font, &span.text);
+    }
+    mask
+}
+
 pub fn title_case(text: &str) -> String {
     text.split_whitespace()
         .map(|word| {
@@ -17,24 +108,35 @@ pub fn title_case(text: &str) -> String {
         .join(" ")
 }
 
+// Replace every `<name>` placeholder with its value
+pub fn replace_variables(text: &str, variables: &[(String, String)]) -> String {
+    let mut processed_text = text.to_string();
+    for (var, value) in variables {
+        processed_text = processed_text.replace(&format!("<{}>", var), value);
+    }
+    processed_text
+}
+
+// Draw `text`, with `**bold**` parts, or add it to `spans` as vector text when they
+// are given. `bold` draws all of it in the bold font, e.g. for titles
+#[allow(clippy::too_many_arguments)]
 pub fn process_text(
     text: &str,
     variables: &[(String, String)],
     font_regular: &FontRef,
     font_bold: &FontRef,
+    bold: bool,
     scale: PxScale,
     layout: &mut RgbImage,
+    mut spans: Option<&mut Vec<TextSpan>>,
     x: u32,
     y: u32,
     alignment: Alignment,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut processed_text = text.to_string();
     let (original_width, _) = text_size(scale, font_regular, text);
 
     // replace variables in text
-    for (var, value) in variables {
-        processed_text = processed_text.replace(&format!("<{}>", var), value);
-    }
+    let processed_text = replace_variables(text, variables);
 
     let clean_text = processed_text.replace("**", "");
     let (clean_width, _) = text_size(scale, font_regular, &clean_text);
@@ -63,17 +165,22 @@ pub fn process_text(
     while i < chars.len() {
         if chars[i] == '*' && i + 1 < chars.len() && chars[i + 1] == '*' {
             // flush the current segment with the current font style
-            let font = if is_bold { font_bold } else { font_regular };
+            let font = if is_bold || bold {
+                font_bold
+            } else {
+                font_regular
+            };
             let (width, _) = text_size(scale, font, &current_segment);
-            draw_text_mut(
+            draw_segment(
                 layout,
-                Rgb([0u8, 0u8, 0u8]),
-                cursor_x.try_into().map_err(|_| "Failed to convert x to u32")?,
-                y.try_into().map_err(|_| "Failed to convert y to u32")?,
-                scale,
+                spans.as_deref_mut(),
                 font,
+                is_bold || bold,
+                scale,
+                cursor_x,
+                y,
                 &current_segment,
-            );
+            )?;
             cursor_x += width;
             current_segment.clear();
 
@@ -87,16 +194,134 @@ pub fn process_text(
     }
 
     if !current_segment.is_empty() {
-        let font = if is_bold { font_bold } else { font_regular };
-        draw_text_mut(
+        let font = if is_bold || bold {
+            font_bold
+        } else {
+            font_regular
+        };
+        draw_segment(
             layout,
-            Rgb([0u8, 0u8, 0u8]),
-            cursor_x.try_into().map_err(|_| "Failed to convert x to u32")?,
-            y.try_into().map_err(|_| "Failed to convert y to u32")?,
-            scale,
+            spans,
             font,
+            is_bold || bold,
+            scale,
+            cursor_x,
+            y,
             &current_segment,
-        );
+        )?;
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGULAR: &[u8] = include_bytes!("../fonts/Roboto-Regular.ttf");
+    const BOLD: &[u8] = include_bytes!("../fonts/Roboto-Bold.ttf");
+
+    fn record(
+        text: &str,
+        font_regular: &FontRef,
+        font_bold: &FontRef,
+        bold: bool,
+    ) -> Vec<TextSpan> {
+        let mut layout = RgbImage::from_pixel(400, 100, Rgb([255, 255, 255]));
+        let mut spans = Vec::new();
+        process_text(
+            text,
+            &[("zone_name".to_string(), "Torraccia".to_string())],
+            font_regular,
+            font_bold,
+            bold,
+            PxScale::from(20.0),
+            &mut layout,
+            Some(&mut spans),
+            10,
+            10,
+            Alignment::Left,
+        )
+        .unwrap();
+        // Recorded text is not drawn
+        assert!(layout.pixels().all(|pixel| pixel.0 == [255, 255, 255]));
+        spans
+    }
+
+    #[test]
+    fn title_cases_words() {
+        assert_eq!(title_case("casal  monastero"), "Casal Monastero");
+        assert_eq!(title_case("àrea nord"), "Àrea Nord");
+    }
+
+    #[test]
+    fn replaces_known_variables_only() {
+        let variables = [("territory_number".to_string(), "12".to_string())];
+        assert_eq!(
+            replace_variables("N. <territory_number> <other>", &variables),
+            "N. 12 <other>"
+        );
+    }
+
+    #[test]
+    fn records_bold_and_regular_spans() {
+        let (regular, bold) = (
+            FontRef::try_from_slice(REGULAR).unwrap(),
+            FontRef::try_from_slice(BOLD).unwrap(),
+        );
+        let spans = record("**ZONA** <zone_name>", &regular, &bold, false);
+        let parts: Vec<_> = spans
+            .iter()
+            .map(|span| (span.text.as_str(), span.bold))
+            .collect();
+        assert_eq!(parts, vec![("ZONA", true), (" Torraccia", false)]);
+        assert!(spans[1].x > spans[0].x);
+    }
+
+    #[test]
+    fn redrawn_spans_match_the_drawn_text() {
+        let (regular, bold) = (
+            FontRef::try_from_slice(REGULAR).unwrap(),
+            FontRef::try_from_slice(BOLD).unwrap(),
+        );
+        let white = Rgb([255u8, 255u8, 255u8]);
+        let mut drawn = RgbImage::from_pixel(400, 100, white);
+        let mut spans = Vec::new();
+        for spans in [None, Some(&mut spans)] {
+            process_text(
+                "**12** <zone_name>",
+                &[("zone_name".to_string(), "Torraccia".to_string())],
+                &regular,
+                &bold,
+                false,
+                PxScale::from(20.0),
+                &mut drawn,
+                spans,
+                10,
+                10,
+                Alignment::Left,
+            )
+            .unwrap();
+        }
+
+        let mut redrawn = RgbImage::from_pixel(400, 100, white);
+        draw_spans(&mut redrawn, &spans, &regular, &bold);
+        assert!(redrawn == drawn);
+        let mask = text_mask(&spans, 400, 100, &regular, &bold);
+        for (pixel, coverage) in drawn.pixels().zip(mask.pixels()) {
+            assert!((255 - pixel[0]).abs_diff(coverage[0]) <= 1);
+        }
+    }
+
+    #[test]
+    fn bold_flag_makes_all_spans_bold() {
+        // Two separately loaded copies of the same font
+        let (first, second) = (
+            FontRef::try_from_slice(BOLD).unwrap(),
+            FontRef::try_from_slice(BOLD).unwrap(),
+        );
+        let spans = record("Legenda <zone_name>", &first, &second, true);
+        assert!(spans.iter().all(|span| span.bold));
+        let spans = record("Legenda <zone_name>", &first, &second, false);
+        assert!(spans.iter().all(|span| !span.bold));
+    }
+}
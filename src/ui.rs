@@ -1,10 +1,17 @@
-use crate::configuration::{AppConfig, ConfigField};
+use crate::build_cache::BuildCache;
+use crate::configuration::{AppConfig, ConfigField, MapSidecar, SidecarField};
+use crate::process_images::{
+    collect_maps, preview_layout, process_map, Failure, MapOutcome, MapSource,
+};
+use crate::terminal_image::{display_image, terminal_cells};
 use crossterm::{
     event::{self, Event, KeyCode, KeyEventKind},
     style::{Color, Stylize},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
+use log::error;
 use std::io::{self, Write};
+use std::path::Path;
 use std::thread;
 use std::time::Duration;
 
@@ -65,7 +72,10 @@ pub fn edit_config(config: &mut AppConfig) {
     // Mapping field display labels to ConfigField variants for dynamic editing
     let config_fields = [
         ("Output Directory", ConfigField::OutputDirectory),
+        ("Output Format", ConfigField::OutputFormat),
+        ("JPEG Quality", ConfigField::JpegQuality),
         ("Maps Directory", ConfigField::MapDirectory),
+        ("Zone From Folder", ConfigField::MapZoneFromFolder),
         ("Font - Regular Path", ConfigField::FontPathRegular),
         ("Font - Bold Path", ConfigField::FontPathBold),
         ("Font - Title Size", ConfigField::FontSizeTitle),
@@ -81,6 +91,11 @@ pub fn edit_config(config: &mut AppConfig) {
         ("Map Crop - Left", ConfigField::MapCropLeft),
         ("Map Crop - Bottom", ConfigField::MapCropBottom),
         ("Map Crop - Right", ConfigField::MapCropRight),
+        ("Map Placement", ConfigField::MapPlacement),
+        ("Map Anchor", ConfigField::MapAnchor),
+        ("Map Resize Filter", ConfigField::MapResizeFilter),
+        ("Supersampling", ConfigField::LayoutSupersample),
+        ("Preview Graphics", ConfigField::PreviewGraphics),
     ];
 
     let mut selected_option = 0;
@@ -116,15 +131,9 @@ pub fn edit_config(config: &mut AppConfig) {
                 continue;
             }
             match key.code {
-                KeyCode::Up => {
-                    if selected_option > 0 {
-                        selected_option -= 1;
-                    }
-                }
-                KeyCode::Down => {
-                    if selected_option < config_fields.len() - 1 {
-                        selected_option += 1;
-                    }
+                KeyCode::Up => selected_option = selected_option.saturating_sub(1),
+                KeyCode::Down if selected_option < config_fields.len() - 1 => {
+                    selected_option += 1;
                 }
                 KeyCode::Enter => {
                     // Temporarily disable raw mode for text input
@@ -147,6 +156,274 @@ pub fn edit_config(config: &mut AppConfig) {
     }
 }
 
+// List the maps that failed during processing, and let the user fix and retry them
+// one at a time until none is left or they go back
+pub fn review_failures(config: &AppConfig, mut failures: Vec<Failure>) {
+    let mut selected_option = 0;
+
+    while !failures.is_empty() {
+        clear_terminal();
+        display_header();
+        println!("\r    Failed maps (Use ↑ ↓ to navigate, Enter to review, Esc to go back)\n");
+        for (index, failure) in failures.iter().enumerate() {
+            let line = format!("{}: {}", failure.map.name.display(), failure.error);
+            if index == selected_option {
+                println!(
+                    "\r\t{}",
+                    format!("> {} <", line).on(Color::Cyan).with(Color::Black)
+                );
+            } else {
+                println!("\r\t{}", format!("  {}", line).with(Color::Red));
+            }
+        }
+
+        if let Event::Key(key) = event::read().expect("Failed to read event") {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Up => selected_option = selected_option.saturating_sub(1),
+                KeyCode::Down if selected_option < failures.len() - 1 => {
+                    selected_option += 1;
+                }
+                KeyCode::Enter if review_failure(config, &mut failures[selected_option]) => {
+                    failures.remove(selected_option);
+                    selected_option = selected_option.min(failures.len().saturating_sub(1));
+                }
+                KeyCode::Esc | KeyCode::Char('q') => return,
+                _ => {}
+            }
+        }
+    }
+
+    clear_terminal();
+    display_header();
+    pause_after_action(
+        "All failed maps are processed now.\n\rPress Enter to return to the menu...",
+    );
+}
+
+// Show one failed map with its crop and name overrides, which are saved to its
+// sidecar when edited, and retry it. Returns true once it is processed
+fn review_failure(config: &AppConfig, failure: &mut Failure) -> bool {
+    let fields = [
+        ("Crop - Top", SidecarField::CropTop),
+        ("Crop - Left", SidecarField::CropLeft),
+        ("Crop - Bottom", SidecarField::CropBottom),
+        ("Crop - Right", SidecarField::CropRight),
+        ("Territory Number", SidecarField::TerritoryNumber),
+        ("Zone Name", SidecarField::ZoneName),
+    ];
+    let retry_option = fields.len();
+    let mut selected_option = 0;
+
+    loop {
+        // A sidecar that doesn't parse can't be edited here, saving would replace it
+        let (mut sidecar, sidecar_error) = match MapSidecar::load(&failure.map.path) {
+            Ok(sidecar) => (sidecar, None),
+            Err(e) => (MapSidecar::default(), Some(e.to_string())),
+        };
+        let parsed = failure.map.parsed_territory(config.map.zone_from_folder);
+        let crop = config.map.crop;
+
+        clear_terminal();
+        display_header();
+        println!(
+            "\r    {} (Use ↑ ↓ to navigate, Enter to edit or retry, Esc to go back)\n",
+            failure.map.name.display()
+        );
+        println!("\r\t{}\n", failure.error.clone().with(Color::Red));
+        for (index, (label, field)) in fields.iter().enumerate() {
+            // Values not set for this map come from the configuration or the filename
+            let value = match sidecar.get_field_value(field) {
+                Some(value) => value,
+                None => {
+                    let default = match field {
+                        SidecarField::CropTop => Some(crop.top.to_string()),
+                        SidecarField::CropLeft => Some(crop.left.to_string()),
+                        SidecarField::CropBottom => Some(crop.bottom.to_string()),
+                        SidecarField::CropRight => Some(crop.right.to_string()),
+                        SidecarField::TerritoryNumber => parsed.clone().map(|(number, _)| number),
+                        SidecarField::ZoneName => parsed.clone().map(|(_, zone)| zone),
+                    };
+                    format!("{} (default)", default.unwrap_or_else(|| "-".to_string()))
+                }
+            };
+            let line = format!("{}: {}", label, value);
+            if index == selected_option {
+                println!(
+                    "\r\t{}",
+                    format!("> {} <", line).on(Color::Cyan).with(Color::Black)
+                );
+            } else {
+                println!("\r\t{}", format!("  {}", line).with(Color::White));
+            }
+        }
+        let retry = "Retry this map";
+        if selected_option == retry_option {
+            println!(
+                "\n\r\t{}",
+                format!("> {} <", retry).on(Color::Cyan).with(Color::Black)
+            );
+        } else {
+            println!("\n\r\t{}", format!("  {}", retry).with(Color::White));
+        }
+        let sidecar_path = MapSidecar::path_for(&failure.map.path);
+        match &sidecar_error {
+            Some(e) => println!(
+                "\n\r{}",
+                format!(
+                    "{} can't be read, fix it in an editor before editing it here - {}",
+                    sidecar_path.display(),
+                    e
+                )
+                .with(Color::Red)
+            ),
+            None => println!(
+                "\n\r{}",
+                format!(
+                    "An empty value goes back to the default. Editing rewrites {} without its comments",
+                    sidecar_path.display()
+                )
+                .with(Color::DarkGrey)
+            ),
+        }
+
+        if let Event::Key(key) = event::read().expect("Failed to read event") {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Up => selected_option = selected_option.saturating_sub(1),
+                KeyCode::Down if selected_option < retry_option => selected_option += 1,
+                KeyCode::Enter if selected_option == retry_option => {
+                    println!("\n\r{}", "Processing...".with(Color::Yellow));
+                    let output_directory = Path::new(&config.output_directory);
+                    let mut cache = BuildCache::load(output_directory);
+                    let outcome = process_map(config, &failure.map, true, &mut cache);
+                    if let Err(e) = cache.save(output_directory) {
+                        error!("{}", e);
+                    }
+                    match outcome {
+                        MapOutcome::Rendered(_) | MapOutcome::Skipped(_) => {
+                            pause_after_action(&format!(
+                                "{} processed.\n\rPress Enter to continue...",
+                                failure.map.name.display()
+                            ));
+                            return true;
+                        }
+                        MapOutcome::Failed(e) => failure.error = e,
+                        MapOutcome::InvalidName => {
+                            failure.error = "Invalid filename format".to_string()
+                        }
+                    }
+                }
+                KeyCode::Enter if sidecar_error.is_none() => {
+                    // Temporarily disable raw mode for text input
+                    disable_raw_mode().expect("Failed to disable raw mode");
+                    let (label, field) = &fields[selected_option];
+                    let new_value = get_new_value(label);
+                    let result = sidecar
+                        .set_field_value(field, new_value, crop)
+                        .and_then(|_| sidecar.save(&failure.map.path).map_err(|e| e.to_string()));
+                    enable_raw_mode().expect("Failed to re-enable raw mode");
+                    if let Err(e) = result {
+                        pause_after_action(&format!(
+                            "{}, nothing was saved.\n\rPress Enter to continue...",
+                            e
+                        ));
+                    }
+                }
+                KeyCode::Esc | KeyCode::Char('q') => return false,
+                _ => {}
+            }
+        }
+    }
+}
+
+// Let the user pick one of the maps, showing as many as fit around the selected one
+pub fn select_map(maps: &[MapSource], title: &str) -> Option<usize> {
+    let mut selected_option: usize = 0;
+
+    loop {
+        clear_terminal();
+        display_header();
+        println!(
+            "\r    {} (Use ↑ ↓ to navigate, Enter to select, Esc to go back)\n",
+            title
+        );
+        // Rows left below the header and the title
+        let (_, rows) = terminal_cells();
+        let visible = (rows as usize).saturating_sub(20).max(5);
+        let first = selected_option
+            .saturating_sub(visible / 2)
+            .min(maps.len().saturating_sub(visible));
+        for (index, map) in maps.iter().enumerate().skip(first).take(visible) {
+            let name = map.name.display();
+            if index == selected_option {
+                println!(
+                    "\r\t{}",
+                    format!("> {} <", name).on(Color::Cyan).with(Color::Black)
+                );
+            } else {
+                println!("\r\t{}", format!("  {}", name).with(Color::White));
+            }
+        }
+
+        if let Event::Key(key) = event::read().expect("Failed to read event") {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Up => selected_option = selected_option.saturating_sub(1),
+                KeyCode::Down if selected_option + 1 < maps.len() => selected_option += 1,
+                KeyCode::Enter => return Some(selected_option),
+                KeyCode::Esc | KeyCode::Char('q') => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+// Render the layout of the territories the user picks and draw it in the terminal
+pub fn preview_territory(config: &AppConfig) {
+    let maps = match collect_maps(Path::new(&config.map.maps_directory)) {
+        Ok(maps) if !maps.is_empty() => maps,
+        Ok(_) => {
+            pause_after_action("No maps found.\n\rPress Enter to return to the menu...");
+            return;
+        }
+        Err(e) => {
+            pause_after_action(&format!(
+                "{}\n\r{}\n\n\r{:#?}",
+                "An error occurred reading the maps :(.", "Press Enter to return to the menu...", e
+            ));
+            return;
+        }
+    };
+
+    while let Some(index) = select_map(&maps, "Preview a territory") {
+        let map = &maps[index];
+        println!("\n\r{}", "Rendering preview...".with(Color::Yellow));
+        let result = preview_layout(config, map).and_then(|layout| {
+            clear_terminal();
+            println!("\r{}", map.name.display().to_string().with(Color::Cyan));
+            // Leave room for the name above and the prompt below the image
+            let (_, rows) = terminal_cells();
+            display_image(&layout, config.preview_graphics, rows.saturating_sub(3))
+        });
+        match result {
+            Ok(()) => pause_after_action("Press Enter to pick another territory..."),
+            Err(e) => pause_after_action(&format!(
+                "{}\n\r{}\n\n\r{:#?}",
+                "An error occurred rendering the preview :(.",
+                "Press Enter to pick another territory...",
+                e
+            )),
+        }
+    }
+}
+
 // Get user input from the terminal
 fn get_user_input() -> String {
     let mut input = String::new();
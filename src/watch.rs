@@ -0,0 +1,247 @@
+use crate::build_cache::BuildCache;
+use crate::configuration::{AppConfig, MapSidecar};
+use crate::logging::open_log_file;
+use crate::process_images::{collect_maps, process_map, remove_stale_files, MapOutcome, MapSource};
+use log::{error, info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const CONFIG_FILE: &str = "config.toml";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Time given to an editor to finish writing a file before it is read
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+// Modification time of every file in the maps directory, its subfolders and the config
+fn snapshot(maps_directory: &Path) -> BTreeMap<PathBuf, SystemTime> {
+    fn walk(directory: &Path, files: &mut BTreeMap<PathBuf, SystemTime>) {
+        let Ok(entries) = fs::read_dir(directory) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path, files);
+            } else if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                files.insert(path, modified);
+            }
+        }
+    }
+
+    let mut files = BTreeMap::new();
+    walk(maps_directory, &mut files);
+    if let Ok(modified) = fs::metadata(CONFIG_FILE).and_then(|m| m.modified()) {
+        files.insert(PathBuf::from(CONFIG_FILE), modified);
+    }
+    files
+}
+
+// Files added, modified or removed between two snapshots
+fn changed_files(
+    before: &BTreeMap<PathBuf, SystemTime>,
+    after: &BTreeMap<PathBuf, SystemTime>,
+) -> BTreeSet<PathBuf> {
+    let mut changed: BTreeSet<PathBuf> = after
+        .iter()
+        .filter(|(path, modified)| before.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.extend(
+        before
+            .keys()
+            .filter(|path| !after.contains_key(*path))
+            .cloned(),
+    );
+    changed
+}
+
+// A map is affected by a change to its sidecar or to one of its tiles, including a
+// tile it had before the change, so deleting a tile renders the rest again
+fn is_affected(map: &MapSource, previous: Option<&MapSource>, changed: &BTreeSet<PathBuf>) -> bool {
+    changed.contains(&MapSidecar::path_for(&map.path))
+        || map
+            .tiles
+            .iter()
+            .chain(previous.iter().flat_map(|previous| &previous.tiles))
+            .any(|tile| changed.contains(tile))
+}
+
+// Whether a map of `previous` is gone from `maps`
+fn map_removed(previous: &[MapSource], maps: &[MapSource]) -> bool {
+    previous
+        .iter()
+        .any(|previous| !maps.iter().any(|map| map.name == previous.name))
+}
+
+// Delete the layouts and thumbnails of maps that are gone, as a full run does
+fn remove_deleted_maps(config: &AppConfig, maps: &[MapSource]) {
+    let output_directory = Path::new(&config.output_directory);
+    let mut cache = BuildCache::load(output_directory);
+    let names: Vec<&Path> = maps.iter().map(|map| map.name.as_path()).collect();
+    remove_stale_files(&cache.retain(&names));
+    if let Err(e) = cache.save(output_directory) {
+        error!("{}", e);
+    }
+}
+
+// Render the given maps and log one line for each
+fn render_maps(config: &AppConfig, maps: &[&MapSource]) {
+    let output_directory = Path::new(&config.output_directory);
+    if let Err(e) = fs::create_dir_all(output_directory) {
+        error!("Failed to create output directory: {}", e);
+        return;
+    }
+    let mut cache = BuildCache::load(output_directory);
+    for map in maps {
+        let start_time = Instant::now();
+        let outcome = process_map(config, map, false, &mut cache);
+        let name = map.name.display();
+        match outcome {
+            MapOutcome::Rendered(rendered) => {
+                let outputs: Vec<String> = rendered
+                    .outputs
+                    .iter()
+                    .map(|page| page.path.display().to_string())
+                    .collect();
+                info!(
+                    "Rendered {} in {:.2?} -> {}",
+                    name,
+                    start_time.elapsed(),
+                    outputs.join(", ")
+                );
+            }
+            MapOutcome::Skipped(_) => info!("Skipped {}, up to date", name),
+            MapOutcome::Failed(e) => error!("Failed to process {}: {}", name, e),
+            MapOutcome::InvalidName => warn!("Invalid filename format: {}", name),
+        }
+    }
+    if let Err(e) = cache.save(output_directory) {
+        error!("{}", e);
+    }
+}
+
+// Watch the maps, their sidecars and the configuration, rendering the territories
+// affected by each change until interrupted. A change to the configuration reloads it
+// and renders every territory whose layouts it changes
+pub fn watch(mut config: AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+    open_log_file(&config)?;
+    println!(
+        "Watching {} and {} (Ctrl+C to stop)",
+        config.map.maps_directory, CONFIG_FILE
+    );
+    let mut files = snapshot(Path::new(&config.map.maps_directory));
+    let mut previous_maps = collect_maps(Path::new(&config.map.maps_directory)).unwrap_or_default();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if changed_files(&files, &snapshot(Path::new(&config.map.maps_directory))).is_empty() {
+            continue;
+        }
+        thread::sleep(SETTLE_TIME);
+        let current = snapshot(Path::new(&config.map.maps_directory));
+        let changed = changed_files(&files, &current);
+        files = current;
+
+        let config_changed = changed.contains(Path::new(CONFIG_FILE));
+        if config_changed {
+            match AppConfig::load() {
+                Ok(new_config) => {
+                    config = new_config;
+                    if let Err(e) = open_log_file(&config) {
+                        error!("{}", e);
+                    }
+                    info!("Reloaded {}", CONFIG_FILE);
+                    // The maps directory itself may have moved
+                    files = snapshot(Path::new(&config.map.maps_directory));
+                }
+                Err(e) => {
+                    error!("Failed to reload {}: {}", CONFIG_FILE, e);
+                    continue;
+                }
+            }
+        }
+
+        let maps = match collect_maps(Path::new(&config.map.maps_directory)) {
+            Ok(maps) => maps,
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            }
+        };
+        let affected: Vec<&MapSource> = maps
+            .iter()
+            .filter(|map| {
+                let previous = previous_maps
+                    .iter()
+                    .find(|previous| previous.name == map.name);
+                config_changed || is_affected(map, previous, &changed)
+            })
+            .collect();
+        render_maps(&config, &affected);
+        if map_removed(&previous_maps, &maps) {
+            remove_deleted_maps(&config, &maps);
+        }
+        previous_maps = maps;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_source(tiles: &[&str]) -> MapSource {
+        MapSource {
+            path: PathBuf::from("maps/12-zone.png"),
+            tiles: tiles
+                .iter()
+                .map(|tile| PathBuf::from("maps").join(tile))
+                .collect(),
+            folder: PathBuf::new(),
+            name: PathBuf::from("12-zone"),
+        }
+    }
+
+    fn changed(paths: &[&str]) -> BTreeSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn changed_files_include_removed_ones() {
+        let time = SystemTime::UNIX_EPOCH;
+        let later = time + Duration::from_secs(1);
+        let before = BTreeMap::from([(PathBuf::from("a"), time), (PathBuf::from("b"), time)]);
+        let after = BTreeMap::from([(PathBuf::from("a"), later), (PathBuf::from("c"), time)]);
+        assert_eq!(changed_files(&before, &after), changed(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn map_is_affected_by_its_files() {
+        let map = map_source(&["12-zone.1.png", "12-zone.2.png"]);
+        assert!(is_affected(&map, None, &changed(&["maps/12-zone.2.png"])));
+        assert!(is_affected(&map, None, &changed(&["maps/12-zone.toml"])));
+        assert!(!is_affected(&map, None, &changed(&["maps/7-zone.png"])));
+    }
+
+    #[test]
+    fn notices_a_removed_map() {
+        let map = map_source(&["12-zone.png"]);
+        let other = MapSource {
+            name: PathBuf::from("7-zone"),
+            ..map_source(&["7-zone.png"])
+        };
+        let (both, one) = ([map.clone(), other.clone()], [other]);
+        assert!(map_removed(&both, &one));
+        assert!(!map_removed(&one, &both));
+        assert!(!map_removed(&both, &both));
+    }
+
+    #[test]
+    fn map_is_affected_by_a_deleted_tile() {
+        let previous = map_source(&["12-zone.1.png", "12-zone.2.png"]);
+        let map = map_source(&["12-zone.1.png"]);
+        let deleted = changed(&["maps/12-zone.2.png"]);
+        assert!(!is_affected(&map, None, &deleted));
+        assert!(is_affected(&map, Some(&previous), &deleted));
+    }
+}